@@ -0,0 +1,63 @@
+//! hooks for running custom code at each stage of [crate::Train::fit]
+
+use crate::{sink::Record, History, Network};
+
+/// whether training should go on after an epoch
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+/// a set of hooks called during training. every hook does nothing by default,
+/// so implementations only need to override the ones they use
+pub trait Callback {
+    /// called once before the first epoch
+    fn on_train_begin(&mut self, _network: &Network) {}
+
+    /// called at the start of every epoch, after the training data has been
+    /// shuffled
+    fn on_epoch_begin(&mut self, _epoch: usize) {}
+
+    /// called before every batch with the learning rate chosen by the
+    /// schedule, which may be changed for this batch
+    fn on_batch_begin(&mut self, _epoch: usize, _batch: usize, _lr: &mut f64) {}
+
+    /// called after every batch with the loss of each sample in it
+    fn on_batch_end(&mut self, _epoch: usize, _batch: usize, _losses: &[f64]) {}
+
+    /// called at the end of every epoch, after validation and checkpointing.
+    /// returning [Control::Stop] ends training after this epoch
+    fn on_epoch_end(
+        &mut self,
+        _record: &Record,
+        _network: &Network,
+    ) -> Control {
+        Control::Continue
+    }
+
+    /// called once after the last epoch, with the metrics from every epoch
+    fn on_train_end(&mut self, _network: &Network, _history: &History) {}
+}
+
+/// prints the seed at the start of training and the validation metric and
+/// time taken after every epoch
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Progress;
+
+impl Callback for Progress {
+    fn on_train_begin(&mut self, network: &Network) {
+        println!("training with seed {}", network.seed());
+    }
+
+    fn on_epoch_end(&mut self, record: &Record, _network: &Network) -> Control {
+        let Record {
+            epoch,
+            val,
+            seconds,
+            ..
+        } = record;
+        println!("{epoch:5} average accuracy {val:.2} in {seconds:.1} s");
+        Control::Continue
+    }
+}
@@ -0,0 +1,401 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::layer::Layer;
+use crate::network::{Best, Node};
+use crate::optimizer::Optimizer;
+use crate::{LossFn, Network};
+
+/// the first line of every checkpoint file
+const MAGIC: &str = "dnnosaur-checkpoint";
+
+/// the version of the checkpoint format written by [Network::save]
+const VERSION: usize = 1;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn write_vec(w: &mut impl Write, v: &[f64]) -> io::Result<()> {
+    write!(w, "{}", v.len())?;
+    for x in v {
+        write!(w, " {x:e}")?;
+    }
+    writeln!(w)
+}
+
+/// the lines of a checkpoint file, split into whitespace-separated fields
+struct Lines<R> {
+    lines: io::Lines<R>,
+    line: usize,
+}
+
+impl<R: BufRead> Lines<R> {
+    fn next(&mut self) -> io::Result<Vec<String>> {
+        self.line += 1;
+        match self.lines.next() {
+            Some(line) => {
+                Ok(line?.split_ascii_whitespace().map(str::to_owned).collect())
+            }
+            None => Err(invalid(format!(
+                "unexpected end of checkpoint at line {}",
+                self.line
+            ))),
+        }
+    }
+
+    /// read a line starting with `key` and return the remaining fields
+    fn expect(&mut self, key: &str) -> io::Result<Vec<String>> {
+        let mut fields = self.next()?;
+        if fields.first().map(String::as_str) != Some(key) {
+            return Err(invalid(format!(
+                "expected `{key}` at line {} of checkpoint",
+                self.line
+            )));
+        }
+        fields.remove(0);
+        Ok(fields)
+    }
+
+    fn parse<T: FromStr>(&self, s: &str) -> io::Result<T> {
+        s.parse().map_err(|_| {
+            invalid(format!(
+                "failed to parse `{s}` at line {} of checkpoint",
+                self.line
+            ))
+        })
+    }
+
+    /// read a line written by [write_vec] after `key`
+    fn vec(&mut self, key: &str) -> io::Result<Vec<f64>> {
+        let fields = self.expect(key)?;
+        let Some((len, rest)) = fields.split_first() else {
+            return Err(invalid(format!(
+                "missing length at line {} of checkpoint",
+                self.line
+            )));
+        };
+        let len: usize = self.parse(len)?;
+        if rest.len() != len {
+            return Err(invalid(format!(
+                "expected {len} values at line {} of checkpoint, found {}",
+                self.line,
+                rest.len()
+            )));
+        }
+        rest.iter().map(|s| self.parse(s)).collect()
+    }
+
+    /// read the single value following `key`
+    fn value<T: FromStr>(&mut self, key: &str) -> io::Result<T> {
+        let fields = self.expect(key)?;
+        match fields.as_slice() {
+            [v] => self.parse(v),
+            _ => Err(invalid(format!(
+                "expected one value at line {} of checkpoint",
+                self.line
+            ))),
+        }
+    }
+}
+
+fn write_state(
+    w: &mut impl Write,
+    key: &str,
+    opt: &dyn Optimizer,
+) -> io::Result<()> {
+    let state = opt.state();
+    writeln!(w, "{key} {}", state.len())?;
+    for s in state {
+        write!(w, "state ")?;
+        write_vec(w, &s)?;
+    }
+    Ok(())
+}
+
+fn read_state<R: BufRead>(
+    lines: &mut Lines<R>,
+    key: &str,
+    opt: &mut dyn Optimizer,
+) -> io::Result<()> {
+    let n: usize = lines.value(key)?;
+    let state = (0..n)
+        .map(|_| lines.vec("state"))
+        .collect::<io::Result<_>>()?;
+    if !opt.set_state(state) {
+        return Err(invalid(format!(
+            "invalid {} state before line {} of checkpoint",
+            opt.name(),
+            lines.line
+        )));
+    }
+    Ok(())
+}
+
+/// read the early-stopping progress written after the layers, checking that
+/// any best parameters fit `nodes`
+fn read_best<R: BufRead>(
+    lines: &mut Lines<R>,
+    nodes: &[Node],
+) -> io::Result<Option<Best>> {
+    let fields = lines.expect("best")?;
+    if fields == ["none"] {
+        return Ok(None);
+    }
+    let [epoch, metric, bad_epochs, n] = fields.as_slice() else {
+        return Err(invalid(format!(
+            "expected epoch, metric, bad epochs, and layers at line {}",
+            lines.line
+        )));
+    };
+    let epoch = lines.parse(epoch)?;
+    let metric = lines.parse(metric)?;
+    let bad_epochs = lines.parse(bad_epochs)?;
+    let n: usize = lines.parse(n)?;
+    if n != 0 && n != nodes.len() {
+        return Err(invalid(format!(
+            "{n} best layers for a network of {} at line {}",
+            nodes.len(),
+            lines.line
+        )));
+    }
+    let mut params = Vec::with_capacity(n);
+    for node in &nodes[..n] {
+        let weights = lines.vec("weights")?;
+        let biases = lines.vec("biases")?;
+        if weights.len() != node.layer.weights.len()
+            || biases.len() != node.layer.biases.len()
+        {
+            return Err(invalid(format!(
+                "best parameter sizes do not match the layer before line {}",
+                lines.line
+            )));
+        }
+        params.push((weights, biases));
+    }
+    Ok(Some(Best {
+        epoch,
+        metric,
+        bad_epochs,
+        params: (n != 0).then_some(params),
+    }))
+}
+
+impl Network {
+    /// write the architecture, parameters, optimizer settings and state,
+    /// schedule state, early-stopping progress, and epoch count of `self` to
+    /// `path`. the format is plain text: a version header followed by one
+    /// block per layer
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        // write to a temporary file and rename it over `path`, so that a crash
+        // partway through never leaves a truncated checkpoint behind
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let file = File::create(&tmp)?;
+        self.write(BufWriter::new(&file))?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)
+    }
+
+    fn write(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "{MAGIC} {VERSION}")?;
+        writeln!(w, "epoch {}", self.epoch)?;
+        writeln!(w, "seed {}", self.seed)?;
+        let optimizer = self.nodes.first().map(|n| n.weight_opt.as_ref());
+        writeln!(w, "optimizer {}", optimizer.map_or("none", |o| o.name()))?;
+        write!(w, "hyperparameters")?;
+        let hyperparameters = optimizer.map(|o| o.hyperparameters());
+        for (name, value) in hyperparameters.unwrap_or_default() {
+            write!(w, " {name} {value:e}")?;
+        }
+        writeln!(w)?;
+        write!(w, "schedule ")?;
+        write_vec(&mut w, &self.schedule_state)?;
+        writeln!(w, "layers {}", self.nodes.len())?;
+        for node in &self.nodes {
+            let Layer {
+                inputs, outputs, ..
+            } = node.layer;
+            let act = node.loss_fn.map_or("none".to_owned(), |l| l.to_string());
+            let dropout = node.dropout;
+            writeln!(w, "layer {inputs} {outputs} {act} {dropout:e}")?;
+            write!(w, "weights ")?;
+            write_vec(&mut w, &node.layer.weights)?;
+            write!(w, "biases ")?;
+            write_vec(&mut w, &node.layer.biases)?;
+            write_state(&mut w, "weight_state", node.weight_opt.as_ref())?;
+            write_state(&mut w, "bias_state", node.bias_opt.as_ref())?;
+        }
+        // the early-stopping progress, followed by the best weights and
+        // biases of every layer if there are any
+        match &self.best {
+            None => writeln!(w, "best none")?,
+            Some(Best {
+                epoch,
+                metric,
+                bad_epochs,
+                params,
+            }) => {
+                let n = params.as_ref().map_or(0, Vec::len);
+                writeln!(w, "best {epoch} {metric:e} {bad_epochs} {n}")?;
+                for (weights, biases) in params.iter().flatten() {
+                    write!(w, "weights ")?;
+                    write_vec(&mut w, weights)?;
+                    write!(w, "biases ")?;
+                    write_vec(&mut w, biases)?;
+                }
+            }
+        }
+        w.flush()
+    }
+
+    /// load a [Network] written by [Network::save]. `optimizer` must be the
+    /// same kind of optimizer that the network was trained with, and its
+    /// saved settings and state are restored into fresh copies of it
+    pub fn load(
+        path: impl AsRef<Path>,
+        optimizer: &dyn Optimizer,
+    ) -> io::Result<Self> {
+        let f = File::open(path)?;
+        let mut lines = Lines {
+            lines: BufReader::new(f).lines(),
+            line: 0,
+        };
+        let version: usize = lines.value(MAGIC)?;
+        if version != VERSION {
+            return Err(invalid(format!(
+                "unsupported checkpoint version {version}, expected {VERSION}"
+            )));
+        }
+        let epoch = lines.value("epoch")?;
+        let seed = lines.value("seed")?;
+        let name: String = lines.value("optimizer")?;
+        if name != optimizer.name() {
+            return Err(invalid(format!(
+                "checkpoint was written with optimizer `{name}`, not `{}`",
+                optimizer.name()
+            )));
+        }
+        // restore the saved settings into the copies of `optimizer` made for
+        // each layer
+        let mut optimizer = optimizer.boxed();
+        let fields = lines.expect("hyperparameters")?;
+        for pair in fields.chunks(2) {
+            let [name, value] = pair else {
+                return Err(invalid(format!(
+                    "missing hyperparameter value at line {} of checkpoint",
+                    lines.line
+                )));
+            };
+            let value = lines.parse(value)?;
+            if !optimizer.set_hyperparameter(name, value) {
+                return Err(invalid(format!(
+                    "`{name}` is not a hyperparameter of `{}` at line {} of \
+                     checkpoint",
+                    optimizer.name(),
+                    lines.line
+                )));
+            }
+        }
+        let schedule_state = lines.vec("schedule")?;
+        let n: usize = lines.value("layers")?;
+        let mut nodes = Vec::with_capacity(n);
+        for _ in 0..n {
+            let fields = lines.expect("layer")?;
+            let [inputs, outputs, act, dropout] = fields.as_slice() else {
+                return Err(invalid(format!(
+                    "expected inputs, outputs, activation, and dropout at \
+                     line {}",
+                    lines.line
+                )));
+            };
+            let inputs: usize = lines.parse(inputs)?;
+            let outputs: usize = lines.parse(outputs)?;
+            let act: Option<LossFn> = match act.as_str() {
+                "none" => None,
+                a => Some(lines.parse(a)?),
+            };
+            let dropout: f64 = lines.parse(dropout)?;
+            let weights = lines.vec("weights")?;
+            let biases = lines.vec("biases")?;
+            if weights.len() != inputs * outputs || biases.len() != outputs {
+                return Err(invalid(format!(
+                    "parameter sizes do not match a {inputs}x{outputs} layer \
+                     before line {}",
+                    lines.line
+                )));
+            }
+            let layer = Layer::from_parts(inputs, outputs, weights, biases);
+            let mut node = Node::new(layer, act, dropout, optimizer.as_ref());
+            read_state(&mut lines, "weight_state", node.weight_opt.as_mut())?;
+            read_state(&mut lines, "bias_state", node.bias_opt.as_mut())?;
+            nodes.push(node);
+        }
+        let best = read_best(&mut lines, &nodes)?;
+        let mut network = Self::from_nodes(nodes, epoch, seed);
+        network.schedule_state = schedule_state;
+        network.best = best;
+        Ok(network)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::Adam;
+    use crate::{Architecture, LossFn};
+
+    #[test]
+    fn test_round_trip() {
+        let arch = Architecture::new()
+            .hidden(4, LossFn::Tanh)
+            .hidden(3, LossFn::LeakyRelu)
+            .dropout(0.25);
+        let opt = Adam::adamw(0.01, 0.1);
+        let mut want = Network::new(5, 2, &arch, &opt, 7);
+        let inputs: Vec<_> = (0..10).map(|i| i as f64 / 10.0).collect();
+        for _ in 0..3 {
+            let out = want.forward(inputs.clone());
+            want.backward(out, 0.01);
+        }
+        want.epoch = 3;
+        want.schedule_state = vec![0.5, 1.25, 2.0];
+        want.best = Some(Best {
+            epoch: 1,
+            metric: 0.75,
+            bad_epochs: 2,
+            params: Some(want.params()),
+        });
+
+        let path = crate::tests::temp_path("round_trip.ckpt");
+        want.save(&path).unwrap();
+        // the temporary file has been renamed into place
+        assert!(!path.with_extension("ckpt.tmp").exists());
+        // a different kind of optimizer is rejected, and the saved settings
+        // replace those of the one passed in
+        assert!(Network::load(&path, &Adam::new(0.01)).is_err());
+        let got = Network::load(&path, &Adam::adamw(0.02, 0.0)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(got.epoch, want.epoch);
+        assert_eq!(got.seed, want.seed);
+        assert_eq!(got.schedule_state, want.schedule_state);
+        assert_eq!(got.best, want.best);
+        for (g, w) in got.nodes.iter().zip(&want.nodes) {
+            assert_eq!(g.loss_fn, w.loss_fn);
+            assert_eq!(g.dropout, w.dropout);
+            assert_eq!(g.layer.weights, w.layer.weights);
+            assert_eq!(g.layer.biases, w.layer.biases);
+            assert_eq!(
+                g.weight_opt.hyperparameters(),
+                w.weight_opt.hyperparameters()
+            );
+            assert_eq!(g.weight_opt.state(), w.weight_opt.state());
+            assert_eq!(g.bias_opt.state(), w.bias_opt.state());
+        }
+        assert_eq!(got.predict(&inputs), want.predict(&inputs));
+    }
+}
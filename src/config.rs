@@ -0,0 +1,175 @@
+use std::path::PathBuf;
+
+use crate::{
+    callback::{Callback, Progress},
+    optimizer::{Optimizer, Sgd},
+    schedule::{Constant, Schedule},
+    sink::Sink,
+    Architecture, Mode,
+};
+
+/// stop training once the validation metric has not improved for `patience`
+/// epochs in a row
+#[derive(Clone, Copy, Debug)]
+pub struct EarlyStopping {
+    /// whether lower or higher validation metrics are better
+    pub mode: Mode,
+    pub patience: usize,
+    /// the smallest change in the metric that counts as an improvement
+    pub min_delta: f64,
+    /// whether to restore the weights from the best epoch when training ends
+    pub restore_best: bool,
+}
+
+impl EarlyStopping {
+    /// stop after `patience` epochs without any improvement, then restore the
+    /// best weights
+    pub fn new(mode: Mode, patience: usize) -> Self {
+        Self {
+            mode,
+            patience,
+            min_delta: 0.0,
+            restore_best: true,
+        }
+    }
+
+    pub fn min_delta(mut self, min_delta: f64) -> Self {
+        self.min_delta = min_delta;
+        self
+    }
+
+    pub fn restore_best(mut self, restore_best: bool) -> Self {
+        self.restore_best = restore_best;
+        self
+    }
+}
+
+/// the settings for a call to [crate::Train::train]
+pub struct Config {
+    pub epochs: usize,
+    pub arch: Architecture,
+    /// the optimizer for a new network. a network passed to
+    /// [crate::Train::fit] keeps the optimizer it was created or loaded with
+    pub optimizer: Box<dyn Optimizer>,
+    pub schedule: Box<dyn Schedule>,
+
+    /// where to save the network after every epoch, if anywhere
+    pub checkpoint: Option<PathBuf>,
+
+    /// whether to visit the training samples in a new random order each epoch
+    pub shuffle: bool,
+
+    /// the seed from which every random choice in a run is derived: the
+    /// initial weights, the order of the training data, and the dropout
+    /// masks. training twice with the same seed gives identical results
+    pub seed: u64,
+
+    /// skip the final batch of each epoch if there are not enough samples
+    /// left to fill it, instead of training on a smaller batch. if there are
+    /// fewer samples than a single batch, that batch is kept anyway
+    pub drop_last: bool,
+
+    /// where to report the metrics from every epoch, in addition to the
+    /// [crate::History] returned by training
+    pub sinks: Vec<Box<dyn Sink>>,
+
+    /// when to stop before `epochs` is reached, if ever
+    pub early_stopping: Option<EarlyStopping>,
+
+    /// hooks called throughout training, in order
+    pub callbacks: Vec<Box<dyn Callback>>,
+
+    /// the number of validation samples to run through the network at once.
+    /// private so that it can only be set through [Config::val_batch_size],
+    /// which rejects zero
+    pub(crate) val_batch_size: usize,
+}
+
+impl Config {
+    /// train a network built from `arch` for `epochs` epochs, using plain SGD
+    /// with a constant learning rate of 0.01, shuffling the training data
+    /// every epoch, and printing the progress after every epoch. the metrics
+    /// are only written to a file if a [crate::sink::Log] is added with
+    /// [Config::sink]
+    pub fn new(epochs: usize, arch: Architecture) -> Self {
+        Self {
+            epochs,
+            arch,
+            optimizer: Box::new(Sgd::new(0.01)),
+            schedule: Box::new(Constant),
+            checkpoint: None,
+            shuffle: true,
+            seed: 410,
+            drop_last: false,
+            sinks: Vec::new(),
+            early_stopping: None,
+            callbacks: vec![Box::new(Progress)],
+            val_batch_size: 1000,
+        }
+    }
+
+    pub fn optimizer(mut self, optimizer: impl Optimizer + 'static) -> Self {
+        self.optimizer = Box::new(optimizer);
+        self
+    }
+
+    pub fn schedule(mut self, schedule: impl Schedule + 'static) -> Self {
+        self.schedule = Box::new(schedule);
+        self
+    }
+
+    pub fn checkpoint(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint = Some(path.into());
+        self
+    }
+
+    pub fn shuffle(mut self, shuffle: bool) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn drop_last(mut self, drop_last: bool) -> Self {
+        self.drop_last = drop_last;
+        self
+    }
+
+    /// report the metrics to `sink` in addition to any existing sinks
+    pub fn sink(mut self, sink: impl Sink + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    pub fn early_stopping(mut self, early_stopping: EarlyStopping) -> Self {
+        self.early_stopping = Some(early_stopping);
+        self
+    }
+
+    /// remove every sink added so far
+    pub fn no_sinks(mut self) -> Self {
+        self.sinks.clear();
+        self
+    }
+
+    pub fn val_batch_size(mut self, val_batch_size: usize) -> Self {
+        assert!(val_batch_size > 0, "validation batch size must be positive");
+        self.val_batch_size = val_batch_size;
+        self
+    }
+
+    /// call `callback` after any existing callbacks
+    pub fn callback(mut self, callback: impl Callback + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// remove every callback, including the default [Progress] printing
+    pub fn no_callbacks(mut self) -> Self {
+        self.callbacks.clear();
+        self
+    }
+}
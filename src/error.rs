@@ -0,0 +1,80 @@
+use std::{
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// an error from loading a dataset
+#[derive(Debug)]
+pub enum Error {
+    /// the file at `path` could not be opened or read
+    Io { path: PathBuf, source: io::Error },
+
+    /// `token`, on line `line` of `path` counting from 1, is not a number
+    Parse {
+        path: PathBuf,
+        line: usize,
+        token: String,
+    },
+
+    /// the contents of `path` do not have the expected size or shape
+    Format { path: PathBuf, message: String },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub(crate) fn io(path: impl AsRef<Path>, source: io::Error) -> Self {
+        Self::Io {
+            path: path.as_ref().to_owned(),
+            source,
+        }
+    }
+
+    pub(crate) fn parse(
+        path: impl AsRef<Path>,
+        line: usize,
+        token: impl Into<String>,
+    ) -> Self {
+        Self::Parse {
+            path: path.as_ref().to_owned(),
+            line,
+            token: token.into(),
+        }
+    }
+
+    pub(crate) fn format(
+        path: impl AsRef<Path>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self::Format {
+            path: path.as_ref().to_owned(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io { path, source } => {
+                write!(f, "{}: {source}", path.display())
+            }
+            Error::Parse { path, line, token } => {
+                write!(f, "{}:{line}: invalid number `{token}`", path.display())
+            }
+            Error::Format { path, message } => {
+                write!(f, "{}: {message}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::sink::{Format, Record};
+
+/// why [crate::Train::fit] stopped training
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Stop {
+    /// the network was trained for `config.epochs` epochs
+    #[default]
+    Completed,
+    /// [crate::EarlyStopping] saw no improvement for `patience` epochs
+    EarlyStopping,
+    /// a [crate::callback::Callback] returned
+    /// [crate::callback::Control::Stop]
+    Callback,
+    /// the training loss of batch `batch` in epoch `epoch` was NaN or
+    /// infinite. that epoch has no record
+    NonFinite { epoch: usize, batch: usize },
+}
+
+/// the metrics from every epoch of a call to [crate::Train::fit]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct History {
+    pub records: Vec<Record>,
+    pub stop: Stop,
+}
+
+impl History {
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// the validation metric from each epoch
+    pub fn val(&self) -> Vec<f64> {
+        self.records.iter().map(|r| r.val).collect()
+    }
+
+    /// the validation loss from each epoch
+    pub fn val_loss(&self) -> Vec<f64> {
+        self.records.iter().map(|r| r.val_loss).collect()
+    }
+
+    /// the mean training metric from each epoch
+    pub fn train(&self) -> Vec<f64> {
+        self.records.iter().map(|r| r.train).collect()
+    }
+
+    /// the mean training loss from each epoch
+    pub fn loss(&self) -> Vec<f64> {
+        self.records.iter().map(|r| r.loss).collect()
+    }
+
+    /// write every record to `w` in `format`
+    pub fn write(&self, mut w: impl Write, format: Format) -> io::Result<()> {
+        let seed = self.records.first().map_or(0, |r| r.seed);
+        format.header(&mut w, seed)?;
+        for r in &self.records {
+            format.write(&mut w, r)?;
+        }
+        w.flush()
+    }
+
+    /// write every record to a new file at `path` in `format`
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        format: Format,
+    ) -> io::Result<()> {
+        self.write(BufWriter::new(File::create(path)?), format)
+    }
+}
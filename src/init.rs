@@ -0,0 +1,199 @@
+use std::{f64::consts::PI, fmt::Debug, sync::Arc};
+
+use rand::{rngs::StdRng, Rng};
+
+/// a function returning one weight given the fan-in and fan-out of its layer
+/// and a random number generator
+pub type InitFn = dyn Fn(usize, usize, &mut StdRng) -> f64 + Send + Sync;
+
+/// a strategy for choosing the initial weights of a dense layer. biases are
+/// always initialized to zero
+#[derive(Clone)]
+pub enum Init {
+    /// uniform in `[-scale, scale]`, regardless of the layer size
+    Uniform(f64),
+    /// Glorot & Bengio, uniform with a limit of `sqrt(6 / (fan_in + fan_out))`
+    XavierUniform,
+    /// Glorot & Bengio, normal with a std. dev. of `sqrt(2 / (fan_in +
+    /// fan_out))`
+    XavierNormal,
+    /// He et al., uniform with a limit of `sqrt(6 / fan_in)`
+    HeUniform,
+    /// He et al., normal with a std. dev. of `sqrt(2 / fan_in)`
+    HeNormal,
+    /// LeCun, uniform with a limit of `sqrt(3 / fan_in)`
+    LeCunUniform,
+    /// LeCun, normal with a std. dev. of `sqrt(1 / fan_in)`
+    LeCunNormal,
+    /// a random (semi-)orthogonal matrix scaled by the gain
+    Orthogonal(f64),
+    /// every weight set to the same value
+    Constant(f64),
+    /// every weight drawn from a user-supplied function
+    Custom(Arc<InitFn>),
+}
+
+impl Debug for Init {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Uniform(s) => write!(f, "Uniform({s})"),
+            Self::XavierUniform => write!(f, "XavierUniform"),
+            Self::XavierNormal => write!(f, "XavierNormal"),
+            Self::HeUniform => write!(f, "HeUniform"),
+            Self::HeNormal => write!(f, "HeNormal"),
+            Self::LeCunUniform => write!(f, "LeCunUniform"),
+            Self::LeCunNormal => write!(f, "LeCunNormal"),
+            Self::Orthogonal(g) => write!(f, "Orthogonal({g})"),
+            Self::Constant(c) => write!(f, "Constant({c})"),
+            Self::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+impl Default for Init {
+    /// the original initialization, uniform in `[-0.2, 0.2]`
+    fn default() -> Self {
+        Self::Uniform(0.2)
+    }
+}
+
+/// a sample from the standard normal distribution, using the Box-Muller
+/// transform
+fn std_normal(rng: &mut StdRng) -> f64 {
+    // 1 - [0, 1) avoids taking the log of 0
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+impl Init {
+    /// an initializer calling `f(fan_in, fan_out, rng)` for every weight
+    pub fn custom(
+        f: impl Fn(usize, usize, &mut StdRng) -> f64 + Send + Sync + 'static,
+    ) -> Self {
+        Self::Custom(Arc::new(f))
+    }
+
+    /// the zero initializer. this is rarely useful for hidden layers, since
+    /// every node then receives the same gradient
+    pub fn zeros() -> Self {
+        Self::Constant(0.0)
+    }
+
+    /// generate the weights for a layer with `inputs` inputs and `outputs`
+    /// outputs, with one row of `outputs` weights per input, drawing from `rng`
+    pub(crate) fn weights(
+        &self,
+        inputs: usize,
+        outputs: usize,
+        rng: &mut StdRng,
+    ) -> Vec<f64> {
+        let (fan_in, fan_out) = (inputs as f64, outputs as f64);
+        let n = inputs * outputs;
+        let uniform = |limit: f64, rng: &mut StdRng| -> Vec<f64> {
+            (0..n).map(|_| limit * rng.gen_range(-1.0..=1.0)).collect()
+        };
+        let normal = |std: f64, rng: &mut StdRng| -> Vec<f64> {
+            (0..n).map(|_| std * std_normal(rng)).collect()
+        };
+        match self {
+            Init::Uniform(scale) => uniform(*scale, rng),
+            Init::XavierUniform => {
+                uniform((6.0 / (fan_in + fan_out)).sqrt(), rng)
+            }
+            Init::HeUniform => uniform((6.0 / fan_in).sqrt(), rng),
+            Init::LeCunUniform => uniform((3.0 / fan_in).sqrt(), rng),
+            Init::XavierNormal => {
+                normal((2.0 / (fan_in + fan_out)).sqrt(), rng)
+            }
+            Init::HeNormal => normal((2.0 / fan_in).sqrt(), rng),
+            Init::LeCunNormal => normal((1.0 / fan_in).sqrt(), rng),
+            Init::Orthogonal(gain) => orthogonal(inputs, outputs, rng)
+                .into_iter()
+                .map(|w| w * gain)
+                .collect(),
+            Init::Constant(c) => vec![*c; n],
+            Init::Custom(f) => {
+                (0..n).map(|_| f(inputs, outputs, rng)).collect()
+            }
+        }
+    }
+}
+
+/// a random `inputs` x `outputs` matrix, stored row-major, whose rows or
+/// columns (whichever there are fewer of) are orthonormal. built by
+/// Gram-Schmidt orthogonalization of normal random vectors
+fn orthogonal(inputs: usize, outputs: usize, rng: &mut StdRng) -> Vec<f64> {
+    // orthogonalize `n` vectors of length `len`, which is at least `n`
+    let (n, len) = if inputs >= outputs {
+        (outputs, inputs)
+    } else {
+        (inputs, outputs)
+    };
+    let mut vecs: Vec<Vec<f64>> = Vec::with_capacity(n);
+    while vecs.len() < n {
+        let mut v: Vec<f64> = (0..len).map(|_| std_normal(rng)).collect();
+        for u in &vecs {
+            let dot: f64 = v.iter().zip(u).map(|(a, b)| a * b).sum();
+            v.iter_mut().zip(u).for_each(|(a, b)| *a -= dot * b);
+        }
+        let norm = v.iter().map(|a| a * a).sum::<f64>().sqrt();
+        // retry in the vanishingly unlikely case of a dependent vector
+        if norm > 1e-8 {
+            v.iter_mut().for_each(|a| *a /= norm);
+            vecs.push(v);
+        }
+    }
+    let mut weights = vec![0.0; inputs * outputs];
+    for (k, v) in vecs.iter().enumerate() {
+        for (j, x) in v.iter().enumerate() {
+            // the vectors are columns when there are more inputs than outputs
+            let (i, o) = if inputs >= outputs { (j, k) } else { (k, j) };
+            weights[outputs * i + o] = *x;
+        }
+    }
+    weights
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn test_orthogonal() {
+        let mut rng = StdRng::seed_from_u64(410);
+        for (inputs, outputs) in [(6, 3), (3, 6), (4, 4)] {
+            let w = Init::Orthogonal(1.0).weights(inputs, outputs, &mut rng);
+            // W^T W = I for tall matrices, W W^T = I for wide ones
+            let n = inputs.min(outputs);
+            for a in 0..n {
+                for b in 0..n {
+                    let dot: f64 = if inputs >= outputs {
+                        (0..inputs)
+                            .map(|i| w[outputs * i + a] * w[outputs * i + b])
+                            .sum()
+                    } else {
+                        (0..outputs)
+                            .map(|o| w[outputs * a + o] * w[outputs * b + o])
+                            .sum()
+                    };
+                    let want = if a == b { 1.0 } else { 0.0 };
+                    assert_abs_diff_eq!(dot, want, epsilon = 1e-12);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_scale() {
+        // the sample variance of He normal weights should be close to 2 /
+        // fan_in
+        let mut rng = StdRng::seed_from_u64(410);
+        let w = Init::HeNormal.weights(200, 300, &mut rng);
+        let var = w.iter().map(|x| x * x).sum::<f64>() / w.len() as f64;
+        assert_abs_diff_eq!(var, 2.0 / 200.0, epsilon = 1e-4);
+    }
+}
@@ -1,48 +1,88 @@
-use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::{init::Init, optimizer::Optimizer};
 
 #[derive(Debug, PartialEq)]
 pub struct LayerGrads {
     pub weight_grads: Vec<f64>,
+    pub bias_grads: Vec<f64>,
     pub input_grads: Vec<f64>,
 }
 
 impl LayerGrads {
-    fn new(weight_grads: Vec<f64>, input_grads: Vec<f64>) -> Self {
+    fn new(
+        weight_grads: Vec<f64>,
+        bias_grads: Vec<f64>,
+        input_grads: Vec<f64>,
+    ) -> Self {
         Self {
             weight_grads,
+            bias_grads,
             input_grads,
         }
     }
 }
 
 pub struct Layer {
-    inputs: usize,
-    outputs: usize,
-    weights: Vec<f64>,
+    pub(crate) inputs: usize,
+    pub(crate) outputs: usize,
+    pub(crate) weights: Vec<f64>,
+    /// one bias per output, initialized to zero
+    pub(crate) biases: Vec<f64>,
     last_inputs: Vec<f64>,
 }
 
 impl Layer {
-    pub fn new(inputs: usize, outputs: usize) -> Self {
-        const SEED: u64 = 410;
-        const SCALE: f64 = 0.2;
-        let mut rng = StdRng::seed_from_u64(SEED);
-        let mut weights = vec![0.0; inputs * outputs];
-        weights.fill_with(|| SCALE * rng.gen_range(-1.0..=1.0));
+    /// a layer with weights chosen by `init`, drawing from `rng`, and zero
+    /// biases
+    pub fn new(
+        inputs: usize,
+        outputs: usize,
+        init: &Init,
+        rng: &mut StdRng,
+    ) -> Self {
         Self {
-            weights,
+            weights: init.weights(inputs, outputs, rng),
+            biases: vec![0.0; outputs],
             last_inputs: Vec::new(),
             inputs,
             outputs,
         }
     }
 
+    /// construct a [Layer] from existing `weights` and `biases`, such as
+    /// those loaded from a checkpoint
+    pub fn from_parts(
+        inputs: usize,
+        outputs: usize,
+        weights: Vec<f64>,
+        biases: Vec<f64>,
+    ) -> Self {
+        assert_eq!(weights.len(), inputs * outputs);
+        assert_eq!(biases.len(), outputs);
+        Self {
+            inputs,
+            outputs,
+            weights,
+            biases,
+            last_inputs: Vec::new(),
+        }
+    }
+
     pub fn forward(&mut self, inputs: Vec<f64>) -> Vec<f64> {
+        let outputs = self.predict(&inputs);
+        self.last_inputs = inputs;
+        outputs
+    }
+
+    /// compute the outputs for `inputs` without caching anything for
+    /// [Layer::backward]
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
         let batch_size = inputs.len() / self.inputs;
         let mut outputs = vec![0.0; batch_size * self.outputs];
         for b in 0..batch_size {
             for o in 0..self.outputs {
-                let mut sum = 0.0;
+                let mut sum = self.biases[o];
                 for i in 0..self.inputs {
                     sum += inputs[b * self.inputs + i]
                         * self.weights[self.outputs * i + o];
@@ -50,17 +90,21 @@ impl Layer {
                 outputs[b * self.outputs + o] = sum;
             }
         }
-        self.last_inputs = inputs;
         outputs
     }
 
     pub fn backward(&self, grads: Vec<f64>) -> LayerGrads {
         let mut weight_grads = vec![0.0; self.inputs * self.outputs];
+        let mut bias_grads = vec![0.0; self.outputs];
 
         let batch_size = self.last_inputs.len() / self.inputs;
         let mut input_grads = vec![0.0; batch_size * self.inputs];
 
         for b in 0..batch_size {
+            for o in 0..self.outputs {
+                bias_grads[o] +=
+                    grads[b * self.outputs + o] / batch_size as f64;
+            }
             for i in 0..self.inputs {
                 for o in 0..self.outputs {
                     weight_grads[i * self.outputs + o] += (grads
@@ -73,25 +117,52 @@ impl Layer {
                 }
             }
         }
-        LayerGrads::new(weight_grads, input_grads)
+        LayerGrads::new(weight_grads, bias_grads, input_grads)
     }
 
-    pub fn apply_gradients(&mut self, grads: Vec<f64>) {
-        for (i, w) in self.weights.iter_mut().enumerate() {
-            const STEP_SIZE: f64 = 0.01;
-            *w -= STEP_SIZE * grads[i];
-        }
+    /// update the weights and biases from `grads` with step size `lr`, using
+    /// a separate optimizer for each since they carry per-parameter state
+    pub fn apply_gradients(
+        &mut self,
+        grads: &LayerGrads,
+        weight_opt: &mut dyn Optimizer,
+        bias_opt: &mut dyn Optimizer,
+        lr: f64,
+    ) {
+        weight_opt.update(&mut self.weights, &grads.weight_grads, lr);
+        bias_opt.update(&mut self.biases, &grads.bias_grads, lr);
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use rand::{Rng, SeedableRng};
+
     use super::*;
+    use crate::optimizer::Sgd;
+
+    #[test]
+    fn test_bias() {
+        let mut layer =
+            Layer::new(3, 2, &Init::default(), &mut StdRng::seed_from_u64(410));
+        layer.forward(vec![0.0; 3]);
+        let grads = layer.backward(vec![1.0, -2.0]);
+        assert_eq!(grads.bias_grads, vec![1.0, -2.0]);
+        let mut sgd = Sgd::new(0.01);
+        layer.apply_gradients(&grads, &mut sgd.clone(), &mut sgd, 0.01);
+        // with zero inputs, only the biases contribute to the outputs
+        assert_eq!(layer.forward(vec![0.0; 3]), vec![-0.01, 0.02]);
+    }
 
     #[test]
     fn test_backward() {
-        let mut layer = Layer::new(20, 10);
         const SEED: u64 = 410;
+        let mut layer = Layer::new(
+            20,
+            10,
+            &Init::Uniform(0.2),
+            &mut StdRng::seed_from_u64(SEED),
+        );
         let mut rng = StdRng::seed_from_u64(SEED);
         let mut weights = vec![0.0; 20];
         weights.fill_with(|| rng.gen_range(-1.0..=1.0));
@@ -301,6 +372,7 @@ mod tests {
                 0.23144508795687802,
                 0.23144508795687802,
             ],
+            bias_grads: vec![0.5; 10],
             input_grads: vec![
                 -0.21110678669221727,
                 0.15695876116686883,
@@ -1,39 +1,142 @@
-use std::io::Write;
-use std::{fs::File, ops::Range};
+use std::fmt::Display;
+use std::io;
+use std::ops::Range;
+use std::str::FromStr;
 
-use layer::Layer;
+use rand::seq::SliceRandom;
+
+use callback::Control;
+use network::Best;
 use nll::NllOutput;
-use relu::{sigmoid, Loss};
+use seed::Stream;
+use sink::{Record, Sink};
 
-use crate::relu::leaky_relu;
+pub use config::{Config, EarlyStopping};
+pub use error::{Error, Result};
+pub use history::{History, Stop};
+pub use network::{Architecture, Hidden, Network};
 
+pub mod callback;
+mod checkpoint;
+mod config;
+mod error;
+mod history;
+pub mod init;
 mod layer;
 pub mod mnist;
+mod network;
+pub mod optimizer;
 pub mod qff;
+pub mod schedule;
+mod seed;
+pub mod sink;
 
-mod nll;
+pub mod nll;
 mod relu;
 
 #[cfg(test)]
 mod tests;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum LossFn {
     LeakyRelu,
     Sigmoid,
     Tanh,
 }
 
-impl From<LossFn> for fn(f64) -> f64 {
-    fn from(value: LossFn) -> Self {
-        match value {
-            LossFn::LeakyRelu => leaky_relu,
-            LossFn::Sigmoid => sigmoid,
-            LossFn::Tanh => f64::tanh,
+impl Display for LossFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LossFn::LeakyRelu => write!(f, "leaky_relu"),
+            LossFn::Sigmoid => write!(f, "sigmoid"),
+            LossFn::Tanh => write!(f, "tanh"),
+        }
+    }
+}
+
+impl FromStr for LossFn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "leaky_relu" => Ok(LossFn::LeakyRelu),
+            "sigmoid" => Ok(LossFn::Sigmoid),
+            "tanh" => Ok(LossFn::Tanh),
+            _ => Err(format!("unrecognized activation function `{s}`")),
+        }
+    }
+}
+
+/// whether lower or higher values of a validation metric are better, such as
+/// [Mode::Min] for an RMSD and [Mode::Max] for an accuracy
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Min,
+    Max,
+}
+
+impl Mode {
+    /// whether `metric` is better than `best` by more than `min_delta`
+    pub fn improved(&self, metric: f64, best: f64, min_delta: f64) -> bool {
+        match self {
+            Mode::Min => metric < best - min_delta,
+            Mode::Max => metric > best + min_delta,
         }
     }
 }
 
-pub trait Train<Label> {
+/// report a failure to write `what`, such as the metrics or a checkpoint,
+/// without interrupting training
+fn warn(what: &str, res: io::Result<()>) {
+    if let Err(e) = res {
+        eprintln!("failed to write {what}: {e}");
+    }
+}
+
+/// collect the inputs and labels for the training samples in `indices` into
+/// contiguous buffers
+fn gather<Label: Clone, T: Train<Label> + ?Sized>(
+    data: &T,
+    indices: &[usize],
+) -> (Vec<f64>, Vec<Label>) {
+    let (is, ls) = (data.input_size(), data.label_size());
+    let mut inputs = Vec::with_capacity(indices.len() * is);
+    let mut labels = Vec::with_capacity(indices.len() * ls);
+    for &i in indices {
+        inputs.extend_from_slice(data.train_data(i * is..(i + 1) * is));
+        labels.extend_from_slice(data.train_labels(i * ls..(i + 1) * ls));
+    }
+    (inputs, labels)
+}
+
+/// run the validation set through `network` in batches of `batch_size`
+/// samples, passing the outputs for each batch to `sinks`, and return the
+/// validation metric and the mean validation loss
+fn validate<Label: Clone, T: Train<Label> + ?Sized>(
+    data: &T,
+    network: &Network,
+    batch_size: usize,
+    epoch: usize,
+    sinks: &mut [Box<dyn Sink>],
+) -> (f64, f64) {
+    let (is, ls) = (data.input_size(), data.label_size());
+    let samples = data.test_labels().len() / ls;
+    let mut metrics = Vec::with_capacity(samples.div_ceil(batch_size));
+    let mut loss = 0.0;
+    for (i, start) in (0..samples).step_by(batch_size).enumerate() {
+        let end = (start + batch_size).min(samples);
+        let outputs = network.predict(&data.test_data()[start * is..end * is]);
+        let want = &data.test_labels()[start * ls..end * ls];
+        for sink in sinks.iter_mut() {
+            warn("metrics", sink.outputs(epoch, i, &outputs));
+        }
+        metrics.push((data.check_output(&outputs, want), data.weight(want)));
+        loss += data.nll(outputs, want).loss.iter().sum::<f64>();
+    }
+    (data.combine(&metrics), loss / samples as f64)
+}
+
+pub trait Train<Label: Clone> {
     fn input_size(&self) -> usize;
     fn output_size(&self) -> usize;
     fn batch_size(&self) -> usize;
@@ -59,76 +162,229 @@ pub trait Train<Label> {
     /// assess the performance of the current output of the model
     fn check_output(&self, got: &[f64], want: &[Label]) -> f64;
 
+    /// the weight given to the result of [Train::check_output] for a batch
+    /// with labels `want` when combining the results of several batches. by
+    /// default, this is the number of samples in the batch
+    fn weight(&self, want: &[Label]) -> f64 {
+        (want.len() / self.label_size()) as f64
+    }
+
+    /// combine the results of [Train::check_output] for several batches,
+    /// given along with their [Train::weight]s, into the result for all of
+    /// the batches at once. by default, this is the weighted mean
+    fn combine(&self, metrics: &[(f64, f64)]) -> f64 {
+        let total: f64 = metrics.iter().map(|(_, w)| w).sum();
+        metrics.iter().map(|(m, w)| m * w).sum::<f64>() / total
+    }
+
     /// the loss function for the model
     fn nll(&self, inputs: Vec<f64>, targets: &[Label]) -> NllOutput;
 
-    /// perform the actual training
-    fn train(&self, epochs: usize, loss_fn: LossFn) -> Vec<f64> {
-        let mut results = Vec::with_capacity(epochs);
+    /// build a fresh [Network] sized for this dataset from the architecture
+    /// and optimizer in `config`
+    fn network(&self, config: &Config) -> Network {
+        Network::new(
+            self.input_size(),
+            self.output_size(),
+            &config.arch,
+            config.optimizer.as_ref(),
+            config.seed,
+        )
+    }
+
+    /// perform the actual training on a fresh network with the settings in
+    /// `config`
+    fn train(&self, config: Config) -> History {
+        let mut network = self.network(&config);
+        self.fit(&mut network, config)
+    }
 
-        const EDGES: usize = 100;
+    /// continue training `network` until it has been trained for
+    /// `config.epochs` epochs in total, such as after loading it with
+    /// [Network::load]. if `config.checkpoint` is set, the network is saved
+    /// there at the end of every epoch. the shuffling and dropout are seeded
+    /// from the seed the network was created with, not `config.seed`, and the
+    /// state of `config.schedule` is restored from the network. the network
+    /// keeps its own optimizer and learning rate, so `config.optimizer` is
+    /// ignored. if
+    /// `config.early_stopping` restores the best weights, they are restored
+    /// into `network` after training, but any checkpoint still holds the
+    /// weights from the last epoch so that training can be resumed. the
+    /// early-stopping progress, including the best weights, is kept with the
+    /// network and its checkpoints, so a resumed run keeps its patience.
+    /// [History::stop] records why training ended
+    fn fit(&self, network: &mut Network, config: Config) -> History {
+        let Config {
+            epochs,
+            mut schedule,
+            checkpoint,
+            shuffle,
+            drop_last,
+            mut sinks,
+            early_stopping,
+            mut callbacks,
+            val_batch_size,
+            ..
+        } = config;
+        let mut history = History::default();
 
-        let mut layer1 = Layer::new(self.input_size(), EDGES);
-        let mut relu1 = Loss::new(loss_fn.into());
-        let mut layer2 = Layer::new(EDGES, self.output_size());
+        let base_lr = network.learning_rate();
+        // dropping the only batch would leave nothing to train on
+        let steps_per_epoch =
+            if drop_last && self.data_size() >= self.batch_size() {
+                self.data_size() / self.batch_size()
+            } else {
+                self.data_size().div_ceil(self.batch_size())
+            };
+        let mut step = network.epoch * steps_per_epoch;
+        // resume a schedule driven by the validation metric where the
+        // checkpoint left it
+        let state = network.schedule_state.clone();
+        if !state.is_empty() && !schedule.set_state(state) {
+            eprintln!("ignoring saved schedule state that does not match");
+        }
+        let mut order: Vec<usize> = (0..self.data_size()).collect();
 
-        let mut output_log = File::create("train.log").unwrap();
-        let mut accuracy_log = File::create("accuracy.log").unwrap();
+        for cb in callbacks.iter_mut() {
+            cb.on_train_begin(network);
+        }
+        for sink in sinks.iter_mut() {
+            warn("metrics", sink.start(network.seed, network.epoch));
+        }
 
-        for e in 0..epochs {
+        'epochs: for e in network.epoch..epochs {
             let now = std::time::Instant::now();
             // training
-            let mut pred_error = 0.0;
-            for i in 0..self.data_size() / self.batch_size() {
-                let inputs = self.train_data(
-                    i * self.input_size() * self.batch_size()
-                        ..(i + 1) * self.input_size() * self.batch_size(),
-                );
-                let targets = &self.train_labels(
-                    i * self.label_size() * self.batch_size()
-                        ..(i + 1) * self.label_size() * self.batch_size(),
-                );
-
-                // Go forward and get loss
-                let outputs1 = layer1.forward(inputs.to_vec());
-                let outputs2 = relu1.forward(outputs1);
-                let outputs3 = layer2.forward(outputs2);
-                pred_error += self.check_output(&outputs3, targets);
-                let loss = self.nll(outputs3, targets);
+            let mut train_metrics = Vec::with_capacity(steps_per_epoch);
+            let (mut total_loss, mut samples) = (0.0, 0);
+            let mut lr = base_lr;
+            if shuffle {
+                // reseed from the epoch so a resumed run sees the same order
+                let mut rng = seed::rng(network.seed, Stream::Shuffle, e);
+                order.sort_unstable();
+                order.shuffle(&mut rng);
+            }
+            network.start_epoch(e);
+            for cb in callbacks.iter_mut() {
+                cb.on_epoch_begin(e);
+            }
+            for i in 0..steps_per_epoch {
+                // the last batch may be short unless `drop_last` is set
+                let end = ((i + 1) * self.batch_size()).min(order.len());
+                let (inputs, targets) =
+                    gather(self, &order[i * self.batch_size()..end]);
+                lr = schedule.learning_rate(base_lr, e, step);
+                for cb in callbacks.iter_mut() {
+                    cb.on_batch_begin(e, i, &mut lr);
+                }
+
+                // Go forward and get loss, stopping before the outputs are
+                // scored if they have diverged
+                let outputs = network.forward(inputs);
+                let loss = self.nll(outputs.clone(), &targets);
+                if loss.loss.iter().any(|l| !l.is_finite()) {
+                    eprintln!(
+                        "non-finite loss in batch {i} of epoch {e}, \
+                         stopping training"
+                    );
+                    history.stop = Stop::NonFinite { epoch: e, batch: i };
+                    break 'epochs;
+                }
+                train_metrics.push((
+                    self.check_output(&outputs, &targets),
+                    self.weight(&targets),
+                ));
+                total_loss += loss.loss.iter().sum::<f64>();
+                samples += loss.loss.len();
 
                 // Update network
-                let grads1 = layer2.backward(loss.input_grads);
-                let grads2 = relu1.backward(grads1.input_grads);
-                let grads3 = layer1.backward(grads2);
-                layer1.apply_gradients(grads3.weight_grads);
-                layer2.apply_gradients(grads1.weight_grads);
+                network.backward(loss.input_grads, lr);
+                step += 1;
+                for cb in callbacks.iter_mut() {
+                    cb.on_batch_end(e, i, &loss.loss);
+                }
+            }
+
+            // validation, without dropout
+            let (res, val_loss) =
+                validate(self, network, val_batch_size, e, &mut sinks);
+            schedule.observe(res);
+
+            let seconds = now.elapsed().as_millis() as f64 / 1000.0;
+            let record = Record {
+                seed: network.seed,
+                epoch: e,
+                val: res,
+                val_loss,
+                train: self.combine(&train_metrics),
+                loss: total_loss / samples as f64,
+                lr,
+                seconds,
+            };
+            for sink in sinks.iter_mut() {
+                warn("metrics", sink.record(&record));
+            }
+
+            history.records.push(record);
+
+            network.epoch = e + 1;
+            network.schedule_state = schedule.state();
+            // update the early-stopping progress before checkpointing, so a
+            // resumed run carries on with the same patience and best weights
+            let mut stop = None;
+            if let Some(es) = &early_stopping {
+                let improved = network.best.as_ref().is_none_or(|b| {
+                    es.mode.improved(res, b.metric, es.min_delta)
+                });
+                if improved {
+                    network.best = Some(Best {
+                        epoch: e,
+                        metric: res,
+                        bad_epochs: 0,
+                        params: es.restore_best.then(|| network.params()),
+                    });
+                } else if let Some(best) = &mut network.best {
+                    best.bad_epochs += 1;
+                    if best.bad_epochs >= es.patience {
+                        stop = Some(Stop::EarlyStopping);
+                    }
+                }
+            }
+            if let Some(path) = &checkpoint {
+                warn("checkpoint", network.save(path));
             }
 
-            // validation
-            let outputs1 = layer1.forward(self.test_data().to_vec());
-            let outputs2 = relu1.forward(outputs1);
-            let outputs3 = layer2.forward(outputs2);
-
-            writeln!(output_log, "{outputs3:#?}").unwrap();
-
-            let res = self.check_output(&outputs3, self.test_labels());
-
-            println!(
-                "{e:5} average accuracy {:.2} in {:.1} s",
-                res,
-                now.elapsed().as_millis() as f64 / 1000.0
-            );
-            writeln!(
-                accuracy_log,
-                "{e:5} {:8.2} {:8.2}",
-                res,
-                pred_error / (self.data_size() / self.batch_size()) as f64
-            )
-            .unwrap();
-
-            results.push(res);
+            // every callback sees the epoch, even if an earlier one stops
+            for cb in callbacks.iter_mut() {
+                if cb.on_epoch_end(&record, network) == Control::Stop {
+                    stop.get_or_insert(Stop::Callback);
+                }
+            }
+            if let Some(stop) = stop {
+                if stop == Stop::EarlyStopping {
+                    let n = network.best.as_ref().map_or(0, |b| b.bad_epochs);
+                    println!("no improvement in {n} epochs, stopping training");
+                }
+                history.stop = stop;
+                break;
+            }
+        }
+
+        if early_stopping.is_some_and(|es| es.restore_best) {
+            if let Some(Best {
+                epoch,
+                params: Some(params),
+                ..
+            }) = network.best.clone()
+            {
+                println!("restoring weights from epoch {epoch}");
+                network.set_params(params);
+            }
+        }
+        for cb in callbacks.iter_mut() {
+            cb.on_train_end(network, &history);
         }
 
-        results
+        history
     }
 }
@@ -1,6 +1,18 @@
-use dnnosaur::{qff::Qff, LossFn, Train};
+use dnnosaur::{
+    qff::Qff,
+    sink::{Format, Log},
+    Config, EarlyStopping, LossFn, Mode, Train,
+};
 
 fn main() {
     // mnist::Data::read_mnist().train(25);
-    Qff::load("qff_data").unwrap().train(200, LossFn::Sigmoid);
+    // validate on the molecule held out in qff_data/test rather than on a
+    // split of the flat files in qff_data, so the validation RMSD measures
+    // generalization to a molecule the network has never seen
+    Qff::load_local("qff_data").unwrap().train(
+        Config::new(200, LossFn::Sigmoid.into())
+            .early_stopping(EarlyStopping::new(Mode::Min, 20))
+            // read by the `plot` script
+            .sink(Log::file("accuracy.log", Format::Plain)),
+    );
 }
@@ -1,6 +1,62 @@
-use std::io::{Read, Seek};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
 
-use crate::{nll::NllOutput, Train};
+use flate2::read::GzDecoder;
+
+use crate::{
+    nll::{LossFunction, NllOutput, SoftmaxCrossEntropy},
+    Error, Result, Train,
+};
+
+/// the IDX type code for unsigned bytes, the only type used by the MNIST
+/// family of datasets
+const UBYTE: u8 = 0x08;
+
+/// the first two bytes of a gzip file
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// the locations of the four files making up an MNIST-style dataset. each may
+/// be either a raw IDX file or a gzip-compressed one
+#[derive(Clone, Debug)]
+pub struct Paths {
+    pub train_images: PathBuf,
+    pub train_labels: PathBuf,
+    pub test_images: PathBuf,
+    pub test_labels: PathBuf,
+}
+
+impl Paths {
+    /// the files in `dir` with their usual names, such as
+    /// `train-images-idx3-ubyte`. a name with a `.gz` suffix is used instead
+    /// if only the compressed file exists
+    pub fn dir(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref();
+        let find = |name: &str| {
+            let path = dir.join(name);
+            let gz = dir.join(format!("{name}.gz"));
+            if !path.exists() && gz.exists() {
+                gz
+            } else {
+                path
+            }
+        };
+        Self {
+            train_images: find("train-images-idx3-ubyte"),
+            train_labels: find("train-labels-idx1-ubyte"),
+            test_images: find("t10k-images-idx3-ubyte"),
+            test_labels: find("t10k-labels-idx1-ubyte"),
+        }
+    }
+}
+
+/// the contents of an IDX file: the size of each dimension and the data, in
+/// row-major order
+struct Idx {
+    dims: Vec<usize>,
+    data: Vec<u8>,
+}
 
 #[derive(Debug, Default)]
 pub struct Data {
@@ -8,46 +64,144 @@ pub struct Data {
     pub train_labels: Vec<u8>,
     pub test_images: Vec<f64>,
     pub test_labels: Vec<u8>,
+
+    input_size: usize,
+    output_size: usize,
+    data_size: usize,
 }
 
 impl Data {
-    pub fn read_mnist(&self) -> Self {
-        let bytes = Self::read_idx_file("data/train-images-idx3-ubyte", 16);
-        assert_eq!(self.input_size() * self.data_size(), bytes.len());
-        let train_images = bytes.iter().map(|&b| b as f64 / 255.0).collect();
+    /// load the dataset from the `data` directory under the current
+    /// directory
+    pub fn read_mnist(&self) -> Result<Self> {
+        Self::load("data")
+    }
 
-        let train_labels =
-            Self::read_idx_file("data/train-labels-idx1-ubyte", 8);
+    /// load the dataset from the usual file names in `dir`, as described by
+    /// [Paths::dir]
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self> {
+        Self::load_paths(&Paths::dir(dir))
+    }
 
-        let bytes = Self::read_idx_file("data/t10k-images-idx3-ubyte", 16);
-        assert_eq!(self.input_size() * 10_000, bytes.len());
-        let test_images = bytes.iter().map(|&b| b as f64 / 255.0).collect();
+    /// load the training and test sets, taking the image size and number of
+    /// samples from the IDX headers and the number of classes from the
+    /// largest label
+    pub fn load_paths(paths: &Paths) -> Result<Self> {
+        let (train_images, train_labels) =
+            Self::read_split(&paths.train_images, &paths.train_labels)?;
+        let (test_images, test_labels) =
+            Self::read_split(&paths.test_images, &paths.test_labels)?;
+        if train_images.dims[1..] != test_images.dims[1..] {
+            return Err(Error::format(
+                &paths.test_images,
+                format!(
+                    "images are {:?}, but the training images are {:?}",
+                    &test_images.dims[1..],
+                    &train_images.dims[1..]
+                ),
+            ));
+        }
+        let pixels = |idx: Idx| -> Vec<f64> {
+            idx.data.iter().map(|&b| b as f64 / 255.0).collect()
+        };
+        let output_size = train_labels
+            .data
+            .iter()
+            .chain(&test_labels.data)
+            .max()
+            .map_or(0, |&l| l as usize + 1);
 
-        let test_labels = Self::read_idx_file("data/t10k-labels-idx1-ubyte", 8);
+        Ok(Self {
+            input_size: train_images.dims[1] * train_images.dims[2],
+            output_size,
+            data_size: train_images.dims[0],
+            training_data: pixels(train_images),
+            train_labels: train_labels.data,
+            test_images: pixels(test_images),
+            test_labels: test_labels.data,
+        })
+    }
 
-        Self {
-            training_data: train_images,
-            train_labels,
-            test_images,
-            test_labels,
+    /// read a file of images and the file of their labels, checking that
+    /// they hold the same number of samples
+    fn read_split(
+        images: impl AsRef<Path>,
+        labels: impl AsRef<Path>,
+    ) -> Result<(Idx, Idx)> {
+        let (images, labels) = (images.as_ref(), labels.as_ref());
+        let i = Self::read_idx_file(images, 3)?;
+        let l = Self::read_idx_file(labels, 1)?;
+        if i.dims[0] != l.dims[0] {
+            return Err(Error::format(
+                labels,
+                format!(
+                    "{} labels for the {} images in {}",
+                    l.dims[0],
+                    i.dims[0],
+                    images.display()
+                ),
+            ));
         }
+        Ok((i, l))
     }
 
-    fn read_idx_file(path: &str, skip: u64) -> Vec<u8> {
-        let mut reader = std::fs::File::open(path).unwrap();
-        reader.seek(std::io::SeekFrom::Start(skip)).unwrap();
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf).unwrap();
-        buf
+    /// read an IDX file of unsigned bytes with `rank` dimensions, checking
+    /// its header against the amount of data that follows. gzip-compressed
+    /// files are decompressed first
+    fn read_idx_file(path: impl AsRef<Path>, rank: usize) -> Result<Idx> {
+        let path = path.as_ref();
+        let mut buf = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+        if buf.starts_with(&GZIP_MAGIC) {
+            let mut raw = Vec::new();
+            GzDecoder::new(buf.as_slice())
+                .read_to_end(&mut raw)
+                .map_err(|e| Error::io(path, e))?;
+            buf = raw;
+        }
+        let invalid = |message: String| Err(Error::format(path, message));
+
+        let header = 4 + 4 * rank;
+        if buf.len() < header {
+            return invalid(format!(
+                "{} bytes is too short for a rank {rank} IDX header",
+                buf.len()
+            ));
+        }
+        let (dtype, ndims) = (buf[2], buf[3]);
+        if buf[0] != 0 || buf[1] != 0 {
+            return invalid("not an IDX file, bad magic number".to_owned());
+        }
+        if dtype != UBYTE {
+            return invalid(format!(
+                "unsupported data type {dtype:#04x}, expected unsigned bytes"
+            ));
+        }
+        if ndims as usize != rank {
+            return invalid(format!("rank {ndims}, expected {rank}"));
+        }
+        let dims: Vec<usize> = buf[4..header]
+            .chunks_exact(4)
+            .map(|d| u32::from_be_bytes([d[0], d[1], d[2], d[3]]) as usize)
+            .collect();
+        let len: usize = dims.iter().product();
+        if buf.len() - header != len {
+            return invalid(format!(
+                "header gives dimensions {dims:?}, needing {len} bytes of \
+                 data, but found {}",
+                buf.len() - header
+            ));
+        }
+        buf.drain(..header);
+        Ok(Idx { dims, data: buf })
     }
 }
 
 impl Train<u8> for Data {
     fn input_size(&self) -> usize {
-        784
+        self.input_size
     }
     fn output_size(&self) -> usize {
-        10
+        self.output_size
     }
     fn batch_size(&self) -> usize {
         32
@@ -56,7 +210,7 @@ impl Train<u8> for Data {
         1
     }
     fn data_size(&self) -> usize {
-        60_000
+        self.data_size
     }
 
     fn train_data(&self, r: std::ops::Range<usize>) -> &[f64] {
@@ -82,46 +236,151 @@ impl Train<u8> for Data {
                 [b * self.output_size()..(b + 1) * self.output_size()]
                 .iter()
                 .enumerate()
-                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
                 .expect("failed to find a max somehow")
                 .0;
             if guess_index as u8 == want[b] {
                 correct += 1;
             }
         }
-        correct as f64 / 100.0
+        100.0 * correct as f64 / want.len() as f64
     }
 
     fn nll(&self, inputs: Vec<f64>, targets: &[u8]) -> NllOutput {
-        let batch_size = targets.len();
-        let mut sum_e = vec![0.0; batch_size];
-        for b in 0..batch_size {
-            let mut sum = 0.0;
-            for i in 0..self.output_size() {
-                sum += inputs[b * self.output_size() + i].exp();
-            }
-            sum_e[b] = sum;
+        // one-hot encode the labels
+        let mut one_hot = vec![0.0; targets.len() * self.output_size()];
+        for (b, &t) in targets.iter().enumerate() {
+            one_hot[b * self.output_size() + t as usize] = 1.0;
         }
+        SoftmaxCrossEntropy.nll(&inputs, &one_hot, None, self.output_size())
+    }
+}
 
-        let mut loss = vec![0.0; batch_size];
-        for b in 0..batch_size {
-            loss[b] = -1.0
-                * (inputs[b * self.output_size() + targets[b] as usize].exp()
-                    / sum_e[b])
-                    .ln();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// write an IDX file of unsigned bytes with `dims` to a temporary file
+    fn idx(name: &str, dims: &[u32], data: &[u8]) -> std::path::PathBuf {
+        let mut buf = vec![0, 0, UBYTE, dims.len() as u8];
+        for d in dims {
+            buf.extend(d.to_be_bytes());
         }
+        buf.extend(data);
+        let path = crate::tests::temp_path(name);
+        std::fs::write(&path, buf).unwrap();
+        path
+    }
 
-        let mut input_grads = vec![0.0; batch_size * self.output_size()];
-        for b in 0..batch_size {
-            for i in 0..self.output_size() {
-                input_grads[b * self.output_size() + i] =
-                    inputs[b * self.output_size() + i].exp() / sum_e[b];
-                if i == targets[b] as usize {
-                    input_grads[b * self.output_size() + i] -= 1.0;
-                }
+    #[test]
+    fn test_idx() {
+        let images = idx("idx_images", &[3, 2, 2], &[7; 12]);
+        let labels = idx("idx_labels", &[3], &[4, 0, 9]);
+        let (i, l) = Data::read_split(&images, &labels).unwrap();
+        assert_eq!(i.dims, vec![3, 2, 2]);
+        assert_eq!(i.data, vec![7; 12]);
+        assert_eq!(l.data, vec![4, 0, 9]);
+
+        // the wrong rank, too little data, and too few labels
+        assert!(Data::read_idx_file(&images, 1).is_err());
+        let short = idx("idx_short", &[3, 2, 2], &[7; 11]);
+        assert!(Data::read_idx_file(&short, 3).is_err());
+        let few = idx("idx_few", &[2], &[4, 0]);
+        assert!(Data::read_split(&images, &few).is_err());
+
+        for p in [images, labels, short, few] {
+            std::fs::remove_file(p).unwrap();
+        }
+    }
+
+    /// a whole dataset loads from a directory of raw and compressed files
+    #[test]
+    fn test_load() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let dir = crate::tests::temp_path("load");
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, dims: &[u32], data: &[u8]| {
+            let path = idx(name, dims, data);
+            if name.ends_with(".gz") {
+                let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+                gz.write_all(&std::fs::read(&path).unwrap()).unwrap();
+                std::fs::write(&path, gz.finish().unwrap()).unwrap();
             }
+            std::fs::rename(path, dir.join(name)).unwrap();
+        };
+        write("train-images-idx3-ubyte.gz", &[4, 2, 3], &[255; 24]);
+        write("train-labels-idx1-ubyte", &[4], &[0, 1, 2, 1]);
+        write("t10k-images-idx3-ubyte.gz", &[2, 2, 3], &[0; 12]);
+        write("t10k-labels-idx1-ubyte.gz", &[2], &[4, 3]);
+
+        let got = Data::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(got.input_size(), 6);
+        assert_eq!(got.output_size(), 5);
+        assert_eq!(got.data_size(), 4);
+        assert_eq!(got.training_data, vec![1.0; 24]);
+        assert_eq!(got.test_images, vec![0.0; 12]);
+        assert_eq!(got.test_labels, vec![4, 3]);
+    }
+
+    /// images of 4 classes, each lighting up its own row of a 4x4 image on
+    /// top of a faint pattern that varies from sample to sample
+    fn synthetic(n: usize) -> (Vec<f64>, Vec<u8>) {
+        let mut images = Vec::with_capacity(16 * n);
+        let mut labels = Vec::with_capacity(n);
+        for s in 0..n {
+            let label = (s * 3 % 4) as u8;
+            images.extend((0..16).map(|p| {
+                let noise = ((s * 7 + p * 5) % 11) as f64 / 40.0;
+                if p / 4 == label as usize {
+                    1.0 - noise
+                } else {
+                    noise
+                }
+            }));
+            labels.push(label);
         }
+        (images, labels)
+    }
+
+    /// training end to end learns a separable dataset, and the same seed
+    /// gives the same run
+    #[test]
+    fn test_train() {
+        use crate::{Config, LossFn};
+
+        let run = || {
+            let (training_data, train_labels) = synthetic(256);
+            let (test_images, test_labels) = synthetic(64);
+            let data = Data {
+                training_data,
+                train_labels,
+                test_images,
+                test_labels,
+                input_size: 16,
+                output_size: 4,
+                data_size: 256,
+            };
+            data.train(Config::new(8, LossFn::LeakyRelu.into()).shuffle(false))
+                .val()
+        };
+        let got = run();
+        assert_eq!(got.len(), 8);
+        assert!(got[0] < 75.0);
+        assert_eq!(got[7], 100.0);
+        assert_eq!(got, run());
+    }
 
-        NllOutput { loss, input_grads }
+    /// diverged outputs are scored rather than panicking
+    #[test]
+    fn test_check_output() {
+        let data = Data {
+            output_size: 3,
+            ..Default::default()
+        };
+        let got = [0.1, 0.7, 0.2, f64::NAN, 0.5, 0.1];
+        assert_eq!(data.check_output(&got, &[1, 2]), 50.0);
     }
 }
@@ -0,0 +1,359 @@
+use rand::{rngs::StdRng, Rng};
+
+use crate::{
+    init::Init,
+    layer::Layer,
+    optimizer::Optimizer,
+    relu::Loss,
+    seed::{self, Stream},
+    LossFn,
+};
+
+/// a hidden layer in an [Architecture]: a dense layer with `width` outputs
+/// followed by `activation`. during training, each output of the activation is
+/// zeroed with probability `dropout`
+#[derive(Clone, Copy, Debug)]
+pub struct Hidden {
+    pub width: usize,
+    pub activation: LossFn,
+    pub dropout: f64,
+}
+
+/// a description of the stack of layers built by [crate::Train::train]. the
+/// size of the first and last dense layers comes from the dataset, so only the
+/// hidden layers between them are listed here. the final dense layer is
+/// followed by `output_activation`, if any. the weights of every layer are
+/// initialized with `init`
+#[derive(Clone, Debug, Default)]
+pub struct Architecture {
+    pub hidden: Vec<Hidden>,
+    pub output_activation: Option<LossFn>,
+    pub init: Init,
+}
+
+impl Architecture {
+    /// an architecture with no hidden layers, equivalent to a single linear
+    /// layer from the inputs to the outputs
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// append a hidden layer of `width` outputs followed by `activation`
+    pub fn hidden(mut self, width: usize, activation: LossFn) -> Self {
+        self.hidden.push(Hidden {
+            width,
+            activation,
+            dropout: 0.0,
+        });
+        self
+    }
+
+    /// apply dropout with probability `rate` to the most recently added hidden
+    /// layer
+    pub fn dropout(mut self, rate: f64) -> Self {
+        assert!((0.0..1.0).contains(&rate), "dropout rate must be in [0, 1)");
+        self.hidden
+            .last_mut()
+            .expect("dropout must follow a hidden layer")
+            .dropout = rate;
+        self
+    }
+
+    /// apply `activation` to the output of the final layer
+    pub fn output(mut self, activation: LossFn) -> Self {
+        self.output_activation = Some(activation);
+        self
+    }
+
+    /// initialize the weights with `init` instead of the default
+    /// [Init::Uniform]
+    pub fn init(mut self, init: Init) -> Self {
+        self.init = init;
+        self
+    }
+}
+
+impl From<LossFn> for Architecture {
+    /// the original network: one hidden layer of 100 nodes using `value` as
+    /// its activation function
+    fn from(value: LossFn) -> Self {
+        Self::new().hidden(100, value)
+    }
+}
+
+/// a dense layer, the activation and dropout applied to its outputs, and the
+/// optimizer state for its weights and biases
+pub(crate) struct Node {
+    pub(crate) layer: Layer,
+    pub(crate) loss_fn: Option<LossFn>,
+    activation: Option<Loss>,
+    pub(crate) dropout: f64,
+    /// the scale applied to each output by dropout in the last forward pass:
+    /// zero for dropped outputs and `1 / (1 - dropout)` for the rest
+    mask: Vec<f64>,
+    pub(crate) weight_opt: Box<dyn Optimizer>,
+    pub(crate) bias_opt: Box<dyn Optimizer>,
+}
+
+impl Node {
+    pub(crate) fn new(
+        layer: Layer,
+        activation: Option<LossFn>,
+        dropout: f64,
+        optimizer: &dyn Optimizer,
+    ) -> Self {
+        Self {
+            layer,
+            loss_fn: activation,
+            activation: activation.map(|a| Loss::new(a.into())),
+            dropout,
+            mask: Vec::new(),
+            weight_opt: optimizer.boxed(),
+            bias_opt: optimizer.boxed(),
+        }
+    }
+}
+
+/// the progress of [crate::EarlyStopping], saved with checkpoints so that a
+/// resumed run keeps its patience and best weights
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Best {
+    /// the epoch with the best validation metric so far
+    pub(crate) epoch: usize,
+    pub(crate) metric: f64,
+    /// the number of epochs since `epoch`
+    pub(crate) bad_epochs: usize,
+    /// the weights and biases from `epoch`, if they are to be restored
+    pub(crate) params: Option<Vec<(Vec<f64>, Vec<f64>)>>,
+}
+
+/// the layers described by an [Architecture], sized for a particular dataset,
+/// along with their optimizer state and the number of epochs trained so far
+pub struct Network {
+    pub(crate) nodes: Vec<Node>,
+    pub(crate) epoch: usize,
+    /// the run-level seed, from which the initial weights and every epoch's
+    /// shuffle and dropout masks are derived
+    pub(crate) seed: u64,
+    /// the generator for dropout masks, reset at the start of every epoch
+    pub(crate) rng: StdRng,
+    /// the state of the learning-rate schedule after `epoch` epochs, saved
+    /// with checkpoints so that [crate::Train::fit] can resume it
+    pub(crate) schedule_state: Vec<f64>,
+    /// the early-stopping progress after `epoch` epochs, if any
+    pub(crate) best: Option<Best>,
+}
+
+impl Network {
+    pub fn new(
+        inputs: usize,
+        outputs: usize,
+        arch: &Architecture,
+        optimizer: &dyn Optimizer,
+        seed: u64,
+    ) -> Self {
+        // a single generator for every layer, so that each one gets different
+        // weights
+        let mut rng = seed::rng(seed, Stream::Init, 0);
+        let mut nodes = Vec::with_capacity(arch.hidden.len() + 1);
+        let mut prev = inputs;
+        for h in &arch.hidden {
+            nodes.push(Node::new(
+                Layer::new(prev, h.width, &arch.init, &mut rng),
+                Some(h.activation),
+                h.dropout,
+                optimizer,
+            ));
+            prev = h.width;
+        }
+        nodes.push(Node::new(
+            Layer::new(prev, outputs, &arch.init, &mut rng),
+            arch.output_activation,
+            0.0,
+            optimizer,
+        ));
+        Self::from_nodes(nodes, 0, seed)
+    }
+
+    pub(crate) fn from_nodes(
+        nodes: Vec<Node>,
+        epoch: usize,
+        seed: u64,
+    ) -> Self {
+        Self {
+            nodes,
+            epoch,
+            seed,
+            rng: seed::rng(seed, Stream::Dropout, epoch),
+            schedule_state: Vec::new(),
+            best: None,
+        }
+    }
+
+    /// the number of epochs this network has been trained for
+    pub fn epoch(&self) -> usize {
+        self.epoch
+    }
+
+    /// the seed this network was created with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// reset the dropout generator for the start of `epoch`
+    pub(crate) fn start_epoch(&mut self, epoch: usize) {
+        self.rng = seed::rng(self.seed, Stream::Dropout, epoch);
+    }
+
+    /// run `inputs` through every layer in order, applying dropout and
+    /// caching the intermediate values needed by [Network::backward]
+    pub(crate) fn forward(&mut self, inputs: Vec<f64>) -> Vec<f64> {
+        let mut outputs = inputs;
+        for node in self.nodes.iter_mut() {
+            outputs = node.layer.forward(outputs);
+            if let Some(act) = node.activation.as_mut() {
+                outputs = act.forward(outputs);
+            }
+            if node.dropout > 0.0 {
+                let keep = 1.0 / (1.0 - node.dropout);
+                node.mask = (0..outputs.len())
+                    .map(|_| {
+                        if self.rng.gen::<f64>() < node.dropout {
+                            0.0
+                        } else {
+                            keep
+                        }
+                    })
+                    .collect();
+                outputs
+                    .iter_mut()
+                    .zip(&node.mask)
+                    .for_each(|(o, m)| *o *= m);
+            }
+        }
+        outputs
+    }
+
+    /// the base learning rate of the optimizer this network is trained with,
+    /// which for a loaded network is the one saved in its checkpoint
+    pub(crate) fn learning_rate(&self) -> f64 {
+        self.nodes
+            .first()
+            .map_or(0.0, |n| n.weight_opt.learning_rate())
+    }
+
+    /// a copy of the weights and biases of every layer
+    pub(crate) fn params(&self) -> Vec<(Vec<f64>, Vec<f64>)> {
+        self.nodes
+            .iter()
+            .map(|n| (n.layer.weights.clone(), n.layer.biases.clone()))
+            .collect()
+    }
+
+    /// restore weights and biases returned by [Network::params]
+    pub(crate) fn set_params(&mut self, params: Vec<(Vec<f64>, Vec<f64>)>) {
+        for (node, (weights, biases)) in self.nodes.iter_mut().zip(params) {
+            node.layer.weights = weights;
+            node.layer.biases = biases;
+        }
+    }
+
+    /// the number of inputs expected for each sample
+    pub fn input_size(&self) -> usize {
+        self.nodes.first().map_or(0, |n| n.layer.inputs)
+    }
+
+    /// the number of outputs produced for each sample
+    pub fn output_size(&self) -> usize {
+        self.nodes.last().map_or(0, |n| n.layer.outputs)
+    }
+
+    /// run a trained network on `inputs`, which may hold any number of
+    /// samples laid out one after another, and return the outputs for each
+    /// sample in the same order. unlike [Network::forward], nothing is cached
+    /// for training and dropout is disabled, so this only needs a shared
+    /// reference
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        assert_eq!(
+            inputs.len() % self.input_size(),
+            0,
+            "input length is not a multiple of the network's input size"
+        );
+        let mut outputs = inputs.to_vec();
+        for node in &self.nodes {
+            outputs = node.layer.predict(&outputs);
+            if let Some(act) = node.activation.as_ref() {
+                outputs = act.predict(&outputs);
+            }
+        }
+        outputs
+    }
+
+    /// propagate the loss gradients `grads` back through the network and
+    /// update the parameters of every layer with step size `lr`
+    pub(crate) fn backward(&mut self, grads: Vec<f64>, lr: f64) {
+        let mut grads = grads;
+        let mut layer_grads = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter().rev() {
+            if node.dropout > 0.0 {
+                grads.iter_mut().zip(&node.mask).for_each(|(g, m)| *g *= m);
+            }
+            if let Some(act) = node.activation.as_ref() {
+                grads = act.backward(grads);
+            }
+            let mut g = node.layer.backward(grads);
+            grads = std::mem::take(&mut g.input_grads);
+            layer_grads.push(g);
+        }
+        for (node, g) in self.nodes.iter_mut().zip(layer_grads.iter().rev()) {
+            node.layer.apply_gradients(
+                g,
+                node.weight_opt.as_mut(),
+                node.bias_opt.as_mut(),
+                lr,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::Sgd;
+
+    #[test]
+    fn test_predict() {
+        let arch = Architecture::new()
+            .hidden(6, LossFn::Sigmoid)
+            .output(LossFn::Tanh);
+        let mut network = Network::new(4, 3, &arch, &Sgd::new(0.01), 410);
+        let inputs: Vec<_> = (0..12).map(|i| i as f64 / 12.0 - 0.5).collect();
+        let got = network.predict(&inputs);
+        assert_eq!(got.len(), 9);
+        assert_eq!(got, network.forward(inputs.clone()));
+        // each sample is predicted independently of the rest of the batch
+        assert_eq!(&got[3..6], network.predict(&inputs[4..8]).as_slice());
+    }
+
+    #[test]
+    fn test_seed() {
+        let arch = Architecture::new().hidden(8, LossFn::Tanh).dropout(0.5);
+        let build = |seed| Network::new(4, 2, &arch, &Sgd::new(0.01), seed);
+        let inputs: Vec<_> = (0..8).map(|i| i as f64 / 8.0).collect();
+        let (mut a, mut b, c) = (build(410), build(410), build(411));
+        assert_eq!(a.nodes[0].layer.weights, b.nodes[0].layer.weights);
+        assert_ne!(a.nodes[0].layer.weights, c.nodes[0].layer.weights);
+        // the layers are drawn from one generator, so they differ
+        assert_ne!(a.nodes[0].layer.weights[..16], a.nodes[1].layer.weights);
+
+        // the same dropout masks are drawn for the same seed and epoch, and
+        // new ones for every batch
+        a.start_epoch(2);
+        b.start_epoch(2);
+        let first = a.forward(inputs.clone());
+        assert_eq!(first, b.forward(inputs.clone()));
+        assert_ne!(first, a.forward(inputs.clone()));
+        // and none at all are used for prediction
+        assert_ne!(first, a.predict(&inputs));
+    }
+}
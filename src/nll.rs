@@ -2,3 +2,312 @@ pub struct NllOutput {
     pub loss: Vec<f64>,
     pub input_grads: Vec<f64>,
 }
+
+/// a loss function comparing the outputs of a network to their targets
+pub trait LossFunction {
+    /// compute the loss of each sample in `outputs` compared to `targets`,
+    /// and the gradient of each sample's loss with respect to its outputs.
+    /// `outputs` and `targets` hold `width` values per sample, and entries
+    /// where `mask` is false are ignored entirely
+    fn nll(
+        &self,
+        outputs: &[f64],
+        targets: &[f64],
+        mask: Option<&[bool]>,
+        width: usize,
+    ) -> NllOutput;
+}
+
+/// the indices of the unmasked entries of sample `b`
+fn unmasked(
+    b: usize,
+    width: usize,
+    mask: Option<&[bool]>,
+) -> impl Iterator<Item = usize> + '_ {
+    (b * width..(b + 1) * width).filter(move |&i| mask.is_none_or(|m| m[i]))
+}
+
+/// apply `f`, which returns the loss and its derivative for a single output
+/// and target, to every unmasked entry, averaging over each sample
+fn elementwise(
+    outputs: &[f64],
+    targets: &[f64],
+    mask: Option<&[bool]>,
+    width: usize,
+    f: impl Fn(f64, f64) -> (f64, f64),
+) -> NllOutput {
+    let batch_size = outputs.len() / width;
+    let mut loss = vec![0.0; batch_size];
+    let mut input_grads = vec![0.0; outputs.len()];
+    for (b, loss) in loss.iter_mut().enumerate() {
+        let n = unmasked(b, width, mask).count();
+        for i in unmasked(b, width, mask) {
+            let (l, g) = f(outputs[i], targets[i]);
+            *loss += l / n as f64;
+            input_grads[i] = g / n as f64;
+        }
+    }
+    NllOutput { loss, input_grads }
+}
+
+/// mean squared error
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Mse;
+
+impl LossFunction for Mse {
+    fn nll(
+        &self,
+        outputs: &[f64],
+        targets: &[f64],
+        mask: Option<&[bool]>,
+        width: usize,
+    ) -> NllOutput {
+        elementwise(outputs, targets, mask, width, |o, t| {
+            let d = o - t;
+            (d * d, 2.0 * d)
+        })
+    }
+}
+
+/// mean absolute error
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Mae;
+
+impl LossFunction for Mae {
+    fn nll(
+        &self,
+        outputs: &[f64],
+        targets: &[f64],
+        mask: Option<&[bool]>,
+        width: usize,
+    ) -> NllOutput {
+        elementwise(outputs, targets, mask, width, |o, t| {
+            let d = o - t;
+            (d.abs(), d.signum())
+        })
+    }
+}
+
+/// the Huber loss: quadratic for errors smaller than `delta` and linear beyond
+/// that
+#[derive(Clone, Copy, Debug)]
+pub struct Huber {
+    pub delta: f64,
+}
+
+impl Huber {
+    pub fn new(delta: f64) -> Self {
+        Self { delta }
+    }
+
+    /// the smooth L1 loss, which is the Huber loss with `delta` = 1
+    pub fn smooth_l1() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl LossFunction for Huber {
+    fn nll(
+        &self,
+        outputs: &[f64],
+        targets: &[f64],
+        mask: Option<&[bool]>,
+        width: usize,
+    ) -> NllOutput {
+        let delta = self.delta;
+        elementwise(outputs, targets, mask, width, |o, t| {
+            let d = o - t;
+            if d.abs() <= delta {
+                (0.5 * d * d, d)
+            } else {
+                (delta * (d.abs() - 0.5 * delta), delta * d.signum())
+            }
+        })
+    }
+}
+
+/// the logarithm of the hyperbolic cosine of the error, which behaves like MSE
+/// for small errors and MAE for large ones
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogCosh;
+
+impl LossFunction for LogCosh {
+    fn nll(
+        &self,
+        outputs: &[f64],
+        targets: &[f64],
+        mask: Option<&[bool]>,
+        width: usize,
+    ) -> NllOutput {
+        elementwise(outputs, targets, mask, width, |o, t| {
+            let d = o - t;
+            // ln(cosh(d)) without overflowing cosh for large d
+            let a = d.abs();
+            let l = a + (-2.0 * a).exp().ln_1p() - std::f64::consts::LN_2;
+            (l, d.tanh())
+        })
+    }
+}
+
+/// binary cross-entropy for outputs that are already probabilities, such as
+/// those from a sigmoid output layer. outputs are clamped away from 0 and 1
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BinaryCrossEntropy;
+
+impl LossFunction for BinaryCrossEntropy {
+    fn nll(
+        &self,
+        outputs: &[f64],
+        targets: &[f64],
+        mask: Option<&[bool]>,
+        width: usize,
+    ) -> NllOutput {
+        const EPS: f64 = 1e-12;
+        elementwise(outputs, targets, mask, width, |o, t| {
+            let p = o.clamp(EPS, 1.0 - EPS);
+            let l = -(t * p.ln() + (1.0 - t) * (1.0 - p).ln());
+            (l, (p - t) / (p * (1.0 - p)))
+        })
+    }
+}
+
+/// the log of the softmax of the unmasked outputs of sample `b`, indexed
+/// relative to the start of the sample. the maximum output is subtracted
+/// before exponentiating so that large outputs cannot overflow
+pub fn log_softmax(
+    outputs: &[f64],
+    b: usize,
+    width: usize,
+    mask: Option<&[bool]>,
+) -> Vec<f64> {
+    let max = unmasked(b, width, mask)
+        .map(|i| outputs[i])
+        .fold(f64::NEG_INFINITY, f64::max);
+    let log_sum = unmasked(b, width, mask)
+        .map(|i| (outputs[i] - max).exp())
+        .sum::<f64>()
+        .ln()
+        + max;
+    let mut log_probs = vec![f64::NEG_INFINITY; width];
+    for i in unmasked(b, width, mask) {
+        log_probs[i - b * width] = outputs[i] - log_sum;
+    }
+    log_probs
+}
+
+/// cross-entropy between the softmax of the outputs and a target distribution
+/// over the classes, usually a one-hot vector
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SoftmaxCrossEntropy;
+
+impl LossFunction for SoftmaxCrossEntropy {
+    fn nll(
+        &self,
+        outputs: &[f64],
+        targets: &[f64],
+        mask: Option<&[bool]>,
+        width: usize,
+    ) -> NllOutput {
+        let batch_size = outputs.len() / width;
+        let mut loss = vec![0.0; batch_size];
+        let mut input_grads = vec![0.0; outputs.len()];
+        for (b, loss) in loss.iter_mut().enumerate() {
+            let log_probs = log_softmax(outputs, b, width, mask);
+            let total: f64 = unmasked(b, width, mask).map(|i| targets[i]).sum();
+            for i in unmasked(b, width, mask) {
+                let log_p = log_probs[i - b * width];
+                if targets[i] != 0.0 {
+                    *loss -= targets[i] * log_p;
+                }
+                input_grads[i] = total * log_p.exp() - targets[i];
+            }
+        }
+        NllOutput { loss, input_grads }
+    }
+}
+
+/// the Kullback-Leibler divergence of the softmax of the outputs from a target
+/// distribution
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KlDivergence;
+
+impl LossFunction for KlDivergence {
+    fn nll(
+        &self,
+        outputs: &[f64],
+        targets: &[f64],
+        mask: Option<&[bool]>,
+        width: usize,
+    ) -> NllOutput {
+        let batch_size = outputs.len() / width;
+        let mut loss = vec![0.0; batch_size];
+        let mut input_grads = vec![0.0; outputs.len()];
+        for (b, loss) in loss.iter_mut().enumerate() {
+            let log_probs = log_softmax(outputs, b, width, mask);
+            let total: f64 = unmasked(b, width, mask).map(|i| targets[i]).sum();
+            for i in unmasked(b, width, mask) {
+                let (log_p, t) = (log_probs[i - b * width], targets[i]);
+                // 0 ln 0 is taken to be 0
+                if t > 0.0 {
+                    *loss += t * (t.ln() - log_p);
+                }
+                input_grads[i] = total * log_p.exp() - t;
+            }
+        }
+        NllOutput { loss, input_grads }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    /// compare the gradients of every loss to central finite differences of
+    /// the per-sample losses, with one masked entry
+    #[test]
+    fn test_gradients() {
+        const H: f64 = 1e-6;
+        let outputs = [0.3, 0.6, 0.9, 0.1, 0.8, 0.45];
+        let targets = [0.0, 1.0, 0.0, 0.2, 0.7, 0.1];
+        let mask = [true, true, true, true, false, true];
+        let losses: Vec<Box<dyn LossFunction>> = vec![
+            Box::new(Mse),
+            Box::new(Mae),
+            Box::new(Huber::new(0.5)),
+            Box::new(LogCosh),
+            Box::new(BinaryCrossEntropy),
+            Box::new(SoftmaxCrossEntropy),
+            Box::new(KlDivergence),
+        ];
+        for loss in losses {
+            let got = loss.nll(&outputs, &targets, Some(&mask), 3);
+            for i in 0..outputs.len() {
+                let mut plus = outputs;
+                plus[i] += H;
+                let mut minus = outputs;
+                minus[i] -= H;
+                let b = i / 3;
+                let want = (loss.nll(&plus, &targets, Some(&mask), 3).loss[b]
+                    - loss.nll(&minus, &targets, Some(&mask), 3).loss[b])
+                    / (2.0 * H);
+                assert_abs_diff_eq!(got.input_grads[i], want, epsilon = 1e-5);
+            }
+        }
+    }
+
+    /// logits large enough to overflow `exp` still give finite losses
+    #[test]
+    fn test_large_logits() {
+        let outputs = [1000.0, 0.0, -1000.0, 800.0, 900.0, 1000.0];
+        let targets = [0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+        let got = SoftmaxCrossEntropy.nll(&outputs, &targets, None, 3);
+        assert_eq!(got.loss, vec![1000.0, 0.0]);
+        assert_abs_diff_eq!(
+            got.input_grads.as_slice(),
+            &[1.0, -1.0, 0.0, 0.0, 0.0, 0.0][..],
+            epsilon = 1e-12
+        );
+    }
+}
@@ -0,0 +1,452 @@
+/// a method for updating parameters from their gradients. each instance holds
+/// the state for a single parameter tensor, so the network asks for a fresh
+/// copy with [Optimizer::boxed] for every weight matrix and bias vector
+pub trait Optimizer {
+    /// a short name identifying this kind of optimizer in checkpoints
+    fn name(&self) -> &'static str;
+
+    /// the learning rate this optimizer was configured with
+    fn learning_rate(&self) -> f64;
+
+    /// every setting of this optimizer by name, for checkpointing. flags are
+    /// given as 0 or 1
+    fn hyperparameters(&self) -> Vec<(&'static str, f64)>;
+
+    /// restore a setting returned by [Optimizer::hyperparameters]. returns
+    /// false if `name` is not a setting of this optimizer
+    fn set_hyperparameter(&mut self, name: &str, value: f64) -> bool;
+
+    /// the per-parameter state accumulated so far, for checkpointing
+    fn state(&self) -> Vec<Vec<f64>>;
+
+    /// restore state previously returned by [Optimizer::state]. returns false
+    /// if `state` does not have the expected shape
+    fn set_state(&mut self, state: Vec<Vec<f64>>) -> bool;
+
+    /// a copy of this optimizer's settings with empty state
+    fn boxed(&self) -> Box<dyn Optimizer>;
+
+    /// update `params` in place from `grads` using step size `lr`
+    fn update(&mut self, params: &mut [f64], grads: &[f64], lr: f64);
+}
+
+/// resize a state buffer to match its parameters the first time it is used
+fn init_state(state: &mut Vec<f64>, len: usize) {
+    if state.len() != len {
+        *state = vec![0.0; len];
+    }
+}
+
+/// stochastic gradient descent with optional (Nesterov) momentum
+#[derive(Clone, Debug)]
+pub struct Sgd {
+    pub lr: f64,
+    pub momentum: f64,
+    pub nesterov: bool,
+    velocity: Vec<f64>,
+}
+
+impl Sgd {
+    /// plain SGD with no momentum
+    pub fn new(lr: f64) -> Self {
+        Self {
+            lr,
+            momentum: 0.0,
+            nesterov: false,
+            velocity: Vec::new(),
+        }
+    }
+
+    /// SGD with classical momentum
+    pub fn momentum(lr: f64, momentum: f64) -> Self {
+        Self {
+            momentum,
+            ..Self::new(lr)
+        }
+    }
+
+    /// SGD with Nesterov momentum
+    pub fn nesterov(lr: f64, momentum: f64) -> Self {
+        Self {
+            momentum,
+            nesterov: true,
+            ..Self::new(lr)
+        }
+    }
+}
+
+impl Optimizer for Sgd {
+    fn name(&self) -> &'static str {
+        "sgd"
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn hyperparameters(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("lr", self.lr),
+            ("momentum", self.momentum),
+            ("nesterov", self.nesterov as u8 as f64),
+        ]
+    }
+
+    fn set_hyperparameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "lr" => self.lr = value,
+            "momentum" => self.momentum = value,
+            "nesterov" => self.nesterov = value != 0.0,
+            _ => return false,
+        }
+        true
+    }
+
+    fn state(&self) -> Vec<Vec<f64>> {
+        vec![self.velocity.clone()]
+    }
+
+    fn set_state(&mut self, state: Vec<Vec<f64>>) -> bool {
+        match <[Vec<f64>; 1]>::try_from(state) {
+            Ok([velocity]) => {
+                self.velocity = velocity;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn boxed(&self) -> Box<dyn Optimizer> {
+        Box::new(Self {
+            velocity: Vec::new(),
+            ..self.clone()
+        })
+    }
+
+    fn update(&mut self, params: &mut [f64], grads: &[f64], lr: f64) {
+        if self.momentum == 0.0 {
+            for (p, g) in params.iter_mut().zip(grads) {
+                *p -= lr * g;
+            }
+            return;
+        }
+        init_state(&mut self.velocity, params.len());
+        for i in 0..params.len() {
+            let v = &mut self.velocity[i];
+            *v = self.momentum * *v + grads[i];
+            if self.nesterov {
+                params[i] -= lr * (grads[i] + self.momentum * *v);
+            } else {
+                params[i] -= lr * *v;
+            }
+        }
+    }
+}
+
+/// RMSProp: scale each step by a moving average of the squared gradients
+#[derive(Clone, Debug)]
+pub struct RmsProp {
+    pub lr: f64,
+    /// decay rate of the moving average
+    pub rho: f64,
+    pub eps: f64,
+    square_avg: Vec<f64>,
+}
+
+impl RmsProp {
+    pub fn new(lr: f64) -> Self {
+        Self {
+            lr,
+            rho: 0.9,
+            eps: 1e-8,
+            square_avg: Vec::new(),
+        }
+    }
+}
+
+impl Optimizer for RmsProp {
+    fn name(&self) -> &'static str {
+        "rmsprop"
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn hyperparameters(&self) -> Vec<(&'static str, f64)> {
+        vec![("lr", self.lr), ("rho", self.rho), ("eps", self.eps)]
+    }
+
+    fn set_hyperparameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "lr" => self.lr = value,
+            "rho" => self.rho = value,
+            "eps" => self.eps = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn state(&self) -> Vec<Vec<f64>> {
+        vec![self.square_avg.clone()]
+    }
+
+    fn set_state(&mut self, state: Vec<Vec<f64>>) -> bool {
+        match <[Vec<f64>; 1]>::try_from(state) {
+            Ok([square_avg]) => {
+                self.square_avg = square_avg;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn boxed(&self) -> Box<dyn Optimizer> {
+        Box::new(Self {
+            square_avg: Vec::new(),
+            ..self.clone()
+        })
+    }
+
+    fn update(&mut self, params: &mut [f64], grads: &[f64], lr: f64) {
+        init_state(&mut self.square_avg, params.len());
+        for i in 0..params.len() {
+            let s = &mut self.square_avg[i];
+            *s = self.rho * *s + (1.0 - self.rho) * grads[i] * grads[i];
+            params[i] -= lr * grads[i] / (s.sqrt() + self.eps);
+        }
+    }
+}
+
+/// Adagrad: scale each step by the accumulated squared gradients
+#[derive(Clone, Debug)]
+pub struct Adagrad {
+    pub lr: f64,
+    pub eps: f64,
+    square_sum: Vec<f64>,
+}
+
+impl Adagrad {
+    pub fn new(lr: f64) -> Self {
+        Self {
+            lr,
+            eps: 1e-10,
+            square_sum: Vec::new(),
+        }
+    }
+}
+
+impl Optimizer for Adagrad {
+    fn name(&self) -> &'static str {
+        "adagrad"
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn hyperparameters(&self) -> Vec<(&'static str, f64)> {
+        vec![("lr", self.lr), ("eps", self.eps)]
+    }
+
+    fn set_hyperparameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "lr" => self.lr = value,
+            "eps" => self.eps = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn state(&self) -> Vec<Vec<f64>> {
+        vec![self.square_sum.clone()]
+    }
+
+    fn set_state(&mut self, state: Vec<Vec<f64>>) -> bool {
+        match <[Vec<f64>; 1]>::try_from(state) {
+            Ok([square_sum]) => {
+                self.square_sum = square_sum;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn boxed(&self) -> Box<dyn Optimizer> {
+        Box::new(Self {
+            square_sum: Vec::new(),
+            ..self.clone()
+        })
+    }
+
+    fn update(&mut self, params: &mut [f64], grads: &[f64], lr: f64) {
+        init_state(&mut self.square_sum, params.len());
+        for i in 0..params.len() {
+            let s = &mut self.square_sum[i];
+            *s += grads[i] * grads[i];
+            params[i] -= lr * grads[i] / (s.sqrt() + self.eps);
+        }
+    }
+}
+
+/// Adam, or AdamW if `decoupled` is set. with `decoupled` false,
+/// `weight_decay` is added to the gradients as an L2 penalty; otherwise it is
+/// applied directly to the parameters
+#[derive(Clone, Debug)]
+pub struct Adam {
+    pub lr: f64,
+    pub beta1: f64,
+    pub beta2: f64,
+    pub eps: f64,
+    pub weight_decay: f64,
+    pub decoupled: bool,
+    /// the number of updates performed so far, for bias correction
+    t: i32,
+    m: Vec<f64>,
+    v: Vec<f64>,
+}
+
+impl Adam {
+    pub fn new(lr: f64) -> Self {
+        Self {
+            lr,
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+            weight_decay: 0.0,
+            decoupled: false,
+            t: 0,
+            m: Vec::new(),
+            v: Vec::new(),
+        }
+    }
+
+    pub fn adamw(lr: f64, weight_decay: f64) -> Self {
+        Self {
+            weight_decay,
+            decoupled: true,
+            ..Self::new(lr)
+        }
+    }
+}
+
+impl Optimizer for Adam {
+    fn name(&self) -> &'static str {
+        if self.decoupled {
+            "adamw"
+        } else {
+            "adam"
+        }
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn hyperparameters(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("lr", self.lr),
+            ("beta1", self.beta1),
+            ("beta2", self.beta2),
+            ("eps", self.eps),
+            ("weight_decay", self.weight_decay),
+            ("decoupled", self.decoupled as u8 as f64),
+        ]
+    }
+
+    fn set_hyperparameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "lr" => self.lr = value,
+            "beta1" => self.beta1 = value,
+            "beta2" => self.beta2 = value,
+            "eps" => self.eps = value,
+            "weight_decay" => self.weight_decay = value,
+            "decoupled" => self.decoupled = value != 0.0,
+            _ => return false,
+        }
+        true
+    }
+
+    fn state(&self) -> Vec<Vec<f64>> {
+        vec![vec![self.t as f64], self.m.clone(), self.v.clone()]
+    }
+
+    fn set_state(&mut self, state: Vec<Vec<f64>>) -> bool {
+        match <[Vec<f64>; 3]>::try_from(state) {
+            Ok([t, m, v]) if t.len() == 1 => {
+                self.t = t[0] as i32;
+                self.m = m;
+                self.v = v;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn boxed(&self) -> Box<dyn Optimizer> {
+        Box::new(Self {
+            t: 0,
+            m: Vec::new(),
+            v: Vec::new(),
+            ..self.clone()
+        })
+    }
+
+    fn update(&mut self, params: &mut [f64], grads: &[f64], lr: f64) {
+        init_state(&mut self.m, params.len());
+        init_state(&mut self.v, params.len());
+        self.t += 1;
+        let c1 = 1.0 - self.beta1.powi(self.t);
+        let c2 = 1.0 - self.beta2.powi(self.t);
+        for i in 0..params.len() {
+            let mut g = grads[i];
+            if self.decoupled {
+                params[i] -= lr * self.weight_decay * params[i];
+            } else {
+                g += self.weight_decay * params[i];
+            }
+            self.m[i] = self.beta1 * self.m[i] + (1.0 - self.beta1) * g;
+            self.v[i] = self.beta2 * self.v[i] + (1.0 - self.beta2) * g * g;
+            let m_hat = self.m[i] / c1;
+            let v_hat = self.v[i] / c2;
+            params[i] -= lr * m_hat / (v_hat.sqrt() + self.eps);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    /// every optimizer should find the minimum of a simple quadratic
+    #[test]
+    fn test_quadratic() {
+        let opts: Vec<Box<dyn Optimizer>> = vec![
+            Box::new(Sgd::new(0.1)),
+            Box::new(Sgd::momentum(0.1, 0.9)),
+            Box::new(Sgd::nesterov(0.1, 0.9)),
+            Box::new(RmsProp::new(0.01)),
+            Box::new(Adagrad::new(0.5)),
+            Box::new(Adam::new(0.05)),
+            Box::new(Adam::adamw(0.05, 1e-4)),
+        ];
+        for opt in opts {
+            let mut opt = opt.boxed();
+            let lr = opt.learning_rate();
+            // f(x) = (x - 3)², f'(x) = 2(x - 3)
+            let mut params = vec![0.0, 6.0];
+            for _ in 0..2000 {
+                let grads: Vec<_> =
+                    params.iter().map(|p| 2.0 * (p - 3.0)).collect();
+                opt.update(&mut params, &grads, lr);
+            }
+            assert_abs_diff_eq!(
+                params.as_slice(),
+                &[3.0, 3.0][..],
+                epsilon = 1e-2
+            );
+        }
+    }
+}
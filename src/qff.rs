@@ -1,19 +1,74 @@
 use std::fmt::Debug;
 use std::fs::File;
-use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::NllOutput;
-use crate::Train;
+use rand::seq::SliceRandom;
+
+use crate::nll::{LossFunction, Mae, Mse, NllOutput};
+use crate::seed::{self, Stream};
+use crate::{Error, Result, Train};
+
+/// a target frequency, along with a mask that is false for the zeros used to
+/// pad smaller molecules out to the size of the largest one. masked-out
+/// frequencies are ignored by the loss and by the RMSD
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Freq {
+    pub value: f64,
+    pub mask: bool,
+}
+
+impl Freq {
+    fn new(value: f64) -> Self {
+        Self { value, mask: true }
+    }
+
+    /// padding for a frequency the molecule does not have
+    fn padding() -> Self {
+        Self {
+            value: 0.0,
+            mask: false,
+        }
+    }
+}
+
+/// the regression loss used to train a [Qff] model by default. both are
+/// averaged over the real (unmasked) frequencies of each molecule. any other
+/// [LossFunction] can be passed to [Qff::with_loss] as well
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Regression {
+    /// mean squared error
+    #[default]
+    Mse,
+    /// mean absolute error
+    Mae,
+}
+
+impl LossFunction for Regression {
+    fn nll(
+        &self,
+        outputs: &[f64],
+        targets: &[f64],
+        mask: Option<&[bool]>,
+        width: usize,
+    ) -> NllOutput {
+        match self {
+            Regression::Mse => Mse.nll(outputs, targets, mask, width),
+            Regression::Mae => Mae.nll(outputs, targets, mask, width),
+        }
+    }
+}
 
-#[derive(Default)]
 pub struct Qff {
     pub train_data: Vec<f64>,
-    pub train_labels: Vec<f64>,
+    pub train_labels: Vec<Freq>,
     pub test_data: Vec<f64>,
-    pub test_labels: Vec<f64>,
+    pub test_labels: Vec<Freq>,
+
+    /// the loss minimized during training, averaged over the real
+    /// frequencies of each molecule
+    pub loss: Box<dyn LossFunction>,
 
     input_size: usize,
     label_size: usize,
@@ -22,12 +77,26 @@ pub struct Qff {
 }
 
 struct Load {
-    freqs: Vec<f64>,
+    freqs: Vec<Freq>,
     lxm: Vec<f64>,
     max_freqs: usize,
     max_row: usize,
     max_col: usize,
-    count: usize,
+}
+
+/// the regular files in `dir`, sorted by name since the order from
+/// [std::fs::read_dir] is unspecified
+fn list_files(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    let mut files = Vec::new();
+    for entry in dir.read_dir().map_err(|e| Error::io(dir, e))? {
+        let path = entry.map_err(|e| Error::io(dir, e))?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
 }
 
 fn transpose(v: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
@@ -40,101 +109,163 @@ fn transpose(v: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
     ret
 }
 
+/// how [Qff::load_pahdb] chooses and divides the files in its directory.
+/// the files are sorted by name, shuffled if `seed` is set, cut down to the
+/// first `limit` files, and then the first `train_fraction` of them are used
+/// for training and the rest for validation
+#[derive(Clone, Copy, Debug)]
+pub struct Split {
+    pub limit: Option<usize>,
+    pub train_fraction: f64,
+    pub seed: Option<u64>,
+}
+
+impl Default for Split {
+    /// every file, in order, with 7/10 used for training
+    fn default() -> Self {
+        Self {
+            limit: None,
+            train_fraction: 0.7,
+            seed: None,
+        }
+    }
+}
+
+impl Split {
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn train_fraction(mut self, train_fraction: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&train_fraction),
+            "training fraction must be in [0, 1]"
+        );
+        self.train_fraction = train_fraction;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+}
+
+impl Default for Qff {
+    fn default() -> Self {
+        Self {
+            train_data: Vec::new(),
+            train_labels: Vec::new(),
+            test_data: Vec::new(),
+            test_labels: Vec::new(),
+            loss: Box::new(Regression::default()),
+            input_size: 0,
+            label_size: 0,
+            output_size: 0,
+            data_size: 0,
+        }
+    }
+}
+
 impl Qff {
-    /// load a [Qff] from the file specified by `p`. This file should contain
-    /// the frequencies on the first line, followed by the lxm matrix
-    pub fn load(dir: impl AsRef<Path>) -> io::Result<Self> {
-        Self::load_pahdb(dir)
-    }
-
-    pub fn load_pahdb(dir: impl AsRef<Path>) -> io::Result<Self> {
-        // load all of the files from the pahdb
-        let home = std::env::var("HOME").unwrap();
-        let home = Path::new(&home);
-        let data_dir = home.join("data/pahdb");
-        let files: Vec<_> =
-            data_dir.read_dir()?.flatten().map(|t| t.path()).collect();
-        let files = &files[..20];
-        // use 7/10 for training and 3/10 for validation
-        let pivot = files.len() * 7 / 10;
-        let train = &files[..pivot];
-        let Load {
-            freqs: train_labels,
-            lxm: train_data,
-            max_freqs,
-            max_row,
-            max_col,
-            count,
-        } = Self::load_files(
-            train.iter().map(|t| dir.as_ref().join(t)).collect(),
-        )?;
+    /// train with `loss` instead of the default [Regression::Mse]
+    pub fn with_loss(mut self, loss: impl LossFunction + 'static) -> Self {
+        self.loss = Box::new(loss);
+        self
+    }
 
-        let test = &files[pivot..];
-        let Load {
-            freqs: test_labels,
-            lxm: test_data,
-            ..
-        } = Self::load_files(
-            test.iter().map(|t| dir.as_ref().join(t)).collect(),
-        )?;
+    /// load a [Qff] from every file in `dir` with the default [Split]. each
+    /// file should contain the frequencies on the first line, followed by the
+    /// lxm matrix
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self> {
+        Self::load_pahdb(dir, &Split::default())
+    }
 
-        Ok(Self {
-            train_data,
-            train_labels,
-            test_data,
-            test_labels,
-            input_size: max_row * max_col,
-            label_size: max_freqs,
-            output_size: max_freqs,
-            data_size: count,
-        })
+    /// load a [Qff] from the files in `dir`, divided into training and
+    /// validation sets according to `split`
+    pub fn load_pahdb(dir: impl AsRef<Path>, split: &Split) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut files = list_files(dir)?;
+        if let Some(seed) = split.seed {
+            files.shuffle(&mut seed::rng(seed, Stream::Split, 0));
+        }
+        if let Some(limit) = split.limit {
+            files.truncate(limit);
+        }
+        let pivot =
+            (files.len() as f64 * split.train_fraction).round() as usize;
+        if pivot == 0 || pivot == files.len() {
+            return Err(Error::format(
+                dir,
+                format!(
+                    "cannot split {} files into non-empty training and \
+                     validation sets with a training fraction of {}",
+                    files.len(),
+                    split.train_fraction
+                ),
+            ));
+        }
+        Self::from_files(files, pivot)
     }
 
-    pub fn load_local(&self, dir: impl AsRef<Path>) -> io::Result<Self> {
-        let train = [
-            //
-            "benzene",
-            "naphthalene",
-            "phenanthrene",
-        ];
+    /// load a [Qff] with the files in `dir/train` as the training set and
+    /// those in `dir/test` as the validation set. a molecule may not appear
+    /// in both. the bundled `qff_data` trains on benzene, c3h2, and
+    /// phenanthrene and validates on naphthalene alone, so its validation
+    /// metric is the RMSD of a single held-out molecule
+    pub fn load_local(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let train = list_files(dir.join("train"))?;
+        let test = list_files(dir.join("test"))?;
+        for t in &test {
+            if train.iter().any(|f| f.file_name() == t.file_name()) {
+                return Err(Error::format(
+                    t,
+                    "this molecule is also in the training set",
+                ));
+            }
+        }
+        if train.is_empty() || test.is_empty() {
+            return Err(Error::format(
+                dir,
+                "the training and validation sets must both be non-empty",
+            ));
+        }
+        let pivot = train.len();
+        Self::from_files(train.into_iter().chain(test).collect(), pivot)
+    }
+
+    /// load every file in `files`, using the first `pivot` for training and
+    /// the rest for validation. they are loaded together so that the training
+    /// and validation sets are padded to the same size
+    fn from_files(files: Vec<PathBuf>, pivot: usize) -> Result<Self> {
         let Load {
-            freqs: train_labels,
-            lxm: train_data,
+            freqs,
+            lxm,
             max_freqs,
             max_row,
             max_col,
-            count,
-        } = Self::load_files(
-            train.iter().map(|t| dir.as_ref().join(t)).collect(),
-        )?;
-
-        let test = [
-            //
-            "benzene",
-            "naphthalene",
-            "phenanthrene",
-        ];
-        let Load {
-            freqs: test_labels,
-            lxm: test_data,
             ..
-        } = Self::load_files(
-            test.iter().map(|t| dir.as_ref().join(t)).collect(),
-        )?;
+        } = Self::load_files(files)?;
+        let input_size = max_row * max_col;
+        let (train_data, test_data) = lxm.split_at(pivot * input_size);
+        let (train_labels, test_labels) = freqs.split_at(pivot * max_freqs);
 
         Ok(Self {
-            train_data,
-            train_labels,
-            test_data,
-            test_labels,
-            input_size: max_row * max_col,
+            train_data: train_data.to_vec(),
+            train_labels: train_labels.to_vec(),
+            test_data: test_data.to_vec(),
+            test_labels: test_labels.to_vec(),
+            input_size,
             label_size: max_freqs,
             output_size: max_freqs,
-            data_size: count,
+            data_size: pivot,
+            ..Default::default()
         })
     }
 
-    fn load_files(files: Vec<impl AsRef<Path> + Debug>) -> io::Result<Load> {
+    fn load_files(files: Vec<impl AsRef<Path> + Debug>) -> Result<Load> {
         let mut freqs = Vec::new();
         let mut lxm = Vec::new();
         let mut max_freqs = 0;
@@ -149,13 +280,13 @@ impl Qff {
             };
             let (or, oc) = max_lxm;
             max_lxm = (or.max(r), oc.max(c));
-            freqs.push(f);
+            freqs.push(f.into_iter().map(Freq::new).collect::<Vec<_>>());
             lxm.push(transpose(l));
         }
         // TODO do the padding here, after loading all of the files and tracking
         // the max for everything. do not do the padding in `load_one`
         for freq in freqs.iter_mut() {
-            freq.resize(max_freqs, 0.0);
+            freq.resize(max_freqs, Freq::padding());
         }
         let (max_row, max_col) = max_lxm;
         for l in lxm.iter_mut() {
@@ -164,46 +295,73 @@ impl Qff {
             }
             l.resize(max_row, vec![0.0; max_col]);
         }
-        let count = freqs.len();
-        assert_eq!(count, lxm.len());
+        assert_eq!(freqs.len(), lxm.len());
         Ok(Load {
             freqs: freqs.into_iter().flatten().collect(),
             lxm: lxm.into_iter().flatten().flatten().collect(),
             max_freqs,
             max_row,
             max_col,
-            count,
         })
     }
 
-    fn load_one(
-        p: impl AsRef<Path>,
-    ) -> Result<(Vec<f64>, Vec<Vec<f64>>), io::Error> {
-        let f = File::open(p)?;
-        let mut lines = BufReader::new(f).lines().flatten();
-        let freqs: Vec<f64> = lines
-            .next()
-            .expect("no lines found")
-            .split_ascii_whitespace()
-            .map(|s| s.parse().unwrap())
-            .collect();
-        let mut lxm = Vec::new();
-        for line in lines {
-            let l: Vec<f64> = line
+    /// load the frequencies and lxm matrix from the file at `p`, reporting
+    /// the line and token of the first value that cannot be parsed
+    fn load_one(p: impl AsRef<Path>) -> Result<(Vec<f64>, Vec<Vec<f64>>)> {
+        let p = p.as_ref();
+        let f = File::open(p).map_err(|e| Error::io(p, e))?;
+        let mut freqs = None;
+        let mut lxm: Vec<Vec<f64>> = Vec::new();
+        for (i, line) in BufReader::new(f).lines().enumerate() {
+            let line = line.map_err(|e| Error::io(p, e))?;
+            let l = line
                 .split_ascii_whitespace()
-                .map(|s| s.parse::<f64>().unwrap())
-                .collect();
+                .map(|s| {
+                    s.parse::<f64>().map_err(|_| Error::parse(p, i + 1, s))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if freqs.is_none() {
+                // a molecule without frequencies would make its RMSD 0/0
+                if l.is_empty() {
+                    return Err(Error::format(
+                        p,
+                        "no frequencies on the first line",
+                    ));
+                }
+                freqs = Some(l);
+                continue;
+            }
             // skip blank lines
             if l.is_empty() {
                 continue;
             }
+            if lxm.first().is_some_and(|r| r.len() != l.len()) {
+                return Err(Error::format(
+                    p,
+                    format!(
+                        "line {} has {} columns, expected {}",
+                        i + 1,
+                        l.len(),
+                        lxm[0].len()
+                    ),
+                ));
+            }
             lxm.push(l);
         }
+        let Some(freqs) = freqs else {
+            return Err(Error::format(p, "empty file"));
+        };
+        if lxm.is_empty() {
+            return Err(Error::format(
+                p,
+                "no lxm matrix after the frequencies",
+            ));
+        }
         Ok((freqs, lxm))
     }
 }
 
-impl Train<f64> for Qff {
+impl Train<Freq> for Qff {
     /// the maximum size of the input lxm matrices
     fn input_size(&self) -> usize {
         self.input_size
@@ -231,7 +389,7 @@ impl Train<f64> for Qff {
         &self.train_data[r]
     }
 
-    fn train_labels(&self, r: std::ops::Range<usize>) -> &[f64] {
+    fn train_labels(&self, r: std::ops::Range<usize>) -> &[Freq] {
         &self.train_labels[r]
     }
 
@@ -239,33 +397,186 @@ impl Train<f64> for Qff {
         &self.test_data
     }
 
-    fn test_labels(&self) -> &[f64] {
+    fn test_labels(&self) -> &[Freq] {
         &self.test_labels
     }
 
-    fn nll(&self, inputs: Vec<f64>, targets: &[f64]) -> NllOutput {
-        let batch_size = targets.len();
-        let mut loss = vec![0.0; batch_size];
-        let mut input_grads = vec![0.0; batch_size * self.output_size()];
-        for b in 0..batch_size {
-            let diff = inputs[b] - targets[b];
-            loss[b] = diff.abs();
-            // damping factor?
-            input_grads[b] = diff / 1000.0;
-        }
-
-        NllOutput { loss, input_grads }
+    fn nll(&self, inputs: Vec<f64>, targets: &[Freq]) -> NllOutput {
+        let values: Vec<_> = targets.iter().map(|t| t.value).collect();
+        let mask: Vec<_> = targets.iter().map(|t| t.mask).collect();
+        self.loss
+            .nll(&inputs, &values, Some(&mask), self.label_size())
     }
 
-    /// return the RMSD of the outputs3 compared to the test labels
-    fn check_output(&self, got: &[f64], want: &[f64]) -> f64 {
+    /// return the RMSD of the outputs3 compared to the test labels, skipping
+    /// padded frequencies
+    fn check_output(&self, got: &[f64], want: &[Freq]) -> f64 {
         let mut sum = 0.0;
         let mut c = 0;
         for (l, o) in want.iter().zip(got) {
-            let diff = l - o;
+            if !l.mask {
+                continue;
+            }
+            let diff = l.value - o;
             sum += diff * diff;
             c += 1;
         }
         (sum / c as f64).sqrt()
     }
+
+    /// the number of real frequencies in the batch, since the RMSD is taken
+    /// over those
+    fn weight(&self, want: &[Freq]) -> f64 {
+        want.iter().filter(|l| l.mask).count() as f64
+    }
+
+    /// the RMSD over every real frequency in the batches
+    fn combine(&self, metrics: &[(f64, f64)]) -> f64 {
+        let total: f64 = metrics.iter().map(|(_, w)| w).sum();
+        (metrics.iter().map(|(m, w)| m * m * w).sum::<f64>() / total).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_mask() {
+        let Load {
+            freqs, max_freqs, ..
+        } = Qff::load_files(vec!["qff_data/benzene", "qff_data/c3h2"]).unwrap();
+        assert_eq!(max_freqs, 30);
+        let real = |f: &[Freq]| f.iter().filter(|f| f.mask).count();
+        assert_eq!(real(&freqs[..30]), 30);
+        assert_eq!(real(&freqs[30..]), 9);
+
+        // predicting the real c3h2 frequencies exactly gives zero RMSD, no
+        // matter what is predicted for the padding
+        let got: Vec<_> = freqs[30..]
+            .iter()
+            .map(|f| if f.mask { f.value } else { 1000.0 })
+            .collect();
+        let qff = Qff::default();
+        assert_eq!(qff.check_output(&got, &freqs[30..]), 0.0);
+    }
+
+    /// every real frequency in the batch gets the gradient of its molecule's
+    /// loss, and padding gets none
+    #[test]
+    fn test_nll() {
+        let targets = [
+            Freq::new(3.0),
+            Freq::new(1.0),
+            Freq::new(4.0),
+            Freq::new(2.0),
+            Freq::padding(),
+            Freq::padding(),
+        ];
+        let inputs = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let qff = Qff {
+            label_size: 3,
+            output_size: 3,
+            ..Default::default()
+        };
+        let got = qff.nll(inputs.clone(), &targets);
+        assert_abs_diff_eq!(
+            got.loss.as_slice(),
+            &[2.0, 4.0][..],
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(
+            got.input_grads.as_slice(),
+            &[-4.0 / 3.0, 2.0 / 3.0, -2.0 / 3.0, 4.0, 0.0, 0.0][..],
+            epsilon = 1e-12
+        );
+
+        let qff = qff.with_loss(Regression::Mae);
+        let got = qff.nll(inputs, &targets);
+        assert_abs_diff_eq!(
+            got.loss.as_slice(),
+            &[4.0 / 3.0, 2.0][..],
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(
+            got.input_grads.as_slice(),
+            &[-1.0 / 3.0, 1.0 / 3.0, -1.0 / 3.0, 1.0, 0.0, 0.0][..],
+            epsilon = 1e-12
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        // benzene and c3h2 come first in sorted order
+        let qff =
+            Qff::load_pahdb("qff_data", &Split::default().train_fraction(0.5))
+                .unwrap();
+        assert_eq!(qff.data_size(), 2);
+        assert_eq!(qff.test_labels.len(), 2 * qff.label_size());
+        assert_eq!(qff.train_data.len(), 2 * qff.input_size());
+        assert_eq!(qff.train_labels[0].value, 2772.0);
+
+        // the same seed picks the same files
+        let split = Split::default().limit(3).seed(7);
+        let a = Qff::load_pahdb("qff_data", &split).unwrap();
+        let b = Qff::load_pahdb("qff_data", &split).unwrap();
+        assert_eq!(a.data_size(), 2);
+        assert_eq!(a.train_labels, b.train_labels);
+        assert_eq!(a.test_labels, b.test_labels);
+
+        let all = Split::default().train_fraction(1.0);
+        assert!(Qff::load_pahdb("qff_data", &all).is_err());
+    }
+
+    #[test]
+    fn test_local() {
+        let qff = Qff::load_local("qff_data").unwrap();
+        assert_eq!(qff.data_size(), 3);
+        assert_eq!(qff.test_labels.len(), qff.label_size());
+        // naphthalene is held out, and has fewer modes than phenanthrene
+        let real = qff.test_labels.iter().filter(|f| f.mask).count();
+        assert_eq!(real, 48);
+
+        // the same molecule cannot be in both sets
+        let dir = crate::tests::temp_path("local");
+        for split in ["train", "test"] {
+            std::fs::create_dir_all(dir.join(split)).unwrap();
+            std::fs::copy("qff_data/c3h2", dir.join(split).join("c3h2"))
+                .unwrap();
+        }
+        let got = Qff::load_local(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(got, Err(Error::Format { .. })));
+    }
+
+    /// malformed files are reported with their path, line, and token
+    /// instead of panicking
+    #[test]
+    fn test_errors() {
+        let path = crate::tests::temp_path("errors.qff");
+        let load = |contents: &str| {
+            std::fs::write(&path, contents).unwrap();
+            Qff::load_one(&path).unwrap_err()
+        };
+        match load("1.0 2.0\n\n0.1 0.2\n0.3 O.4\n") {
+            Error::Parse { line, token, .. } => {
+                assert_eq!((line, token.as_str()), (4, "O.4"))
+            }
+            e => panic!("unexpected error {e}"),
+        }
+        assert!(matches!(load(""), Error::Format { .. }));
+        assert!(matches!(load("\n0.1 0.2\n"), Error::Format { .. }));
+        assert!(matches!(load("1.0\n0.1 0.2\n0.3\n"), Error::Format { .. }));
+        std::fs::remove_file(&path).unwrap();
+
+        match Qff::load_one("qff_data/missing").unwrap_err() {
+            Error::Io { path, source } => {
+                assert_eq!(path, Path::new("qff_data/missing"));
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            e => panic!("unexpected error {e}"),
+        }
+    }
 }
@@ -1,8 +1,34 @@
 #![allow(unused)]
 
+use crate::LossFn;
+
+/// an activation function paired with its derivative, both evaluated at the
+/// input to the activation
+#[derive(Clone, Copy)]
+pub struct Activation {
+    fun: fn(f64) -> f64,
+    deriv: fn(f64) -> f64,
+}
+
+impl Activation {
+    pub fn new(fun: fn(f64) -> f64, deriv: fn(f64) -> f64) -> Self {
+        Self { fun, deriv }
+    }
+}
+
+impl From<LossFn> for Activation {
+    fn from(value: LossFn) -> Self {
+        match value {
+            LossFn::LeakyRelu => Self::new(leaky_relu, leaky_relu_deriv),
+            LossFn::Sigmoid => Self::new(sigmoid, sigmoid_deriv),
+            LossFn::Tanh => Self::new(f64::tanh, tanh_deriv),
+        }
+    }
+}
+
 pub struct Loss {
     last_inputs: Vec<f64>,
-    fun: fn(f64) -> f64,
+    act: Activation,
 }
 
 pub fn leaky_relu(x: f64) -> f64 {
@@ -13,33 +39,74 @@ pub fn leaky_relu(x: f64) -> f64 {
     }
 }
 
+pub fn leaky_relu_deriv(x: f64) -> f64 {
+    if x < 0.0 {
+        0.01
+    } else {
+        1.0
+    }
+}
+
 pub fn sigmoid(x: f64) -> f64 {
     1.0 / (1.0 + (-x).exp())
 }
 
+pub fn sigmoid_deriv(x: f64) -> f64 {
+    let s = sigmoid(x);
+    s * (1.0 - s)
+}
+
+pub fn tanh_deriv(x: f64) -> f64 {
+    let t = x.tanh();
+    1.0 - t * t
+}
+
 impl Loss {
-    pub fn new(fun: fn(f64) -> f64) -> Self {
+    pub fn new(act: Activation) -> Self {
         Self {
-            fun,
+            act,
             last_inputs: Vec::new(),
         }
     }
 
     pub fn forward(&mut self, inputs: Vec<f64>) -> Vec<f64> {
+        let outputs = self.predict(&inputs);
         self.last_inputs = inputs;
-        self.last_inputs.iter().map(|&i| (self.fun)(i)).collect()
+        outputs
+    }
+
+    /// apply the activation to `inputs` without caching them for
+    /// [Loss::backward]
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        inputs.iter().map(|&i| (self.act.fun)(i)).collect()
     }
 
     pub fn backward(&self, grads: Vec<f64>) -> Vec<f64> {
         let mut outputs = vec![0.0; grads.len()];
         for i in 0..self.last_inputs.len() {
-            if self.last_inputs[i] < 0.0 {
-                // NOTE zig code says grads[i] = but that doesn't make any sense
-                outputs[i] = 0.01 * grads[i];
-            } else {
-                outputs[i] = grads[i]
-            }
+            outputs[i] = (self.act.deriv)(self.last_inputs[i]) * grads[i];
         }
         outputs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    /// compare the analytic derivative of each activation to a central finite
+    /// difference
+    #[test]
+    fn test_derivatives() {
+        const H: f64 = 1e-6;
+        for loss_fn in [LossFn::LeakyRelu, LossFn::Sigmoid, LossFn::Tanh] {
+            let act = Activation::from(loss_fn);
+            for x in [-3.0, -0.5, 0.25, 2.0] {
+                let want = ((act.fun)(x + H) - (act.fun)(x - H)) / (2.0 * H);
+                assert_abs_diff_eq!((act.deriv)(x), want, epsilon = 1e-6);
+            }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::f64::consts::PI;
+
+use crate::Mode;
+
+/// a learning-rate schedule, consulted by [crate::Train::train] before every
+/// update
+pub trait Schedule {
+    /// the learning rate to use for update number `step` (counted across the
+    /// whole run) in `epoch`, given the optimizer's initial rate `base`
+    fn learning_rate(&mut self, base: f64, epoch: usize, step: usize) -> f64;
+
+    /// record the validation metric at the end of an epoch. only schedules
+    /// driven by the metric need to override this
+    fn observe(&mut self, _metric: f64) {}
+
+    /// the state accumulated by [Schedule::observe], for checkpointing
+    fn state(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    /// restore state returned by [Schedule::state]. returns false if `state`
+    /// does not have the expected shape
+    fn set_state(&mut self, state: Vec<f64>) -> bool {
+        state.is_empty()
+    }
+}
+
+/// use the optimizer's learning rate unchanged
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Constant;
+
+impl Schedule for Constant {
+    fn learning_rate(&mut self, base: f64, _epoch: usize, _step: usize) -> f64 {
+        base
+    }
+}
+
+/// multiply the learning rate by `gamma` every `step_size` epochs. a
+/// `step_size` of zero is treated as one
+#[derive(Clone, Copy, Debug)]
+pub struct StepDecay {
+    pub step_size: usize,
+    pub gamma: f64,
+}
+
+impl Schedule for StepDecay {
+    fn learning_rate(&mut self, base: f64, epoch: usize, _step: usize) -> f64 {
+        base * self.gamma.powi((epoch / self.step_size.max(1)) as i32)
+    }
+}
+
+/// multiply the learning rate by `gamma` every epoch
+#[derive(Clone, Copy, Debug)]
+pub struct Exponential {
+    pub gamma: f64,
+}
+
+impl Schedule for Exponential {
+    fn learning_rate(&mut self, base: f64, epoch: usize, _step: usize) -> f64 {
+        base * self.gamma.powi(epoch as i32)
+    }
+}
+
+/// cosine annealing from the base rate down to `min_lr` over `period` epochs,
+/// restarting at the base rate at the end of each period. each period is
+/// `t_mult` times longer than the last
+#[derive(Clone, Copy, Debug)]
+pub struct CosineRestarts {
+    pub period: usize,
+    pub t_mult: usize,
+    pub min_lr: f64,
+}
+
+impl Schedule for CosineRestarts {
+    fn learning_rate(&mut self, base: f64, epoch: usize, _step: usize) -> f64 {
+        let mut cur = epoch;
+        let mut period = self.period.max(1);
+        while cur >= period {
+            cur -= period;
+            period *= self.t_mult.max(1);
+        }
+        let frac = cur as f64 / period as f64;
+        self.min_lr + 0.5 * (base - self.min_lr) * (1.0 + (PI * frac).cos())
+    }
+}
+
+/// ramp the learning rate linearly from zero over the first `steps` updates,
+/// then defer to `after`
+pub struct Warmup {
+    pub steps: usize,
+    pub after: Box<dyn Schedule>,
+}
+
+impl Schedule for Warmup {
+    fn learning_rate(&mut self, base: f64, epoch: usize, step: usize) -> f64 {
+        let lr = self.after.learning_rate(base, epoch, step);
+        if step < self.steps {
+            lr * (step + 1) as f64 / self.steps as f64
+        } else {
+            lr
+        }
+    }
+
+    fn observe(&mut self, metric: f64) {
+        self.after.observe(metric);
+    }
+
+    fn state(&self) -> Vec<f64> {
+        self.after.state()
+    }
+
+    fn set_state(&mut self, state: Vec<f64>) -> bool {
+        self.after.set_state(state)
+    }
+}
+
+/// multiply the learning rate by `factor` whenever the validation metric has
+/// not improved by more than `min_delta` for `patience` epochs, without going
+/// below `min_lr`
+#[derive(Clone, Debug)]
+pub struct ReduceOnPlateau {
+    pub mode: Mode,
+    pub factor: f64,
+    pub patience: usize,
+    pub min_delta: f64,
+    pub min_lr: f64,
+    scale: f64,
+    best: Option<f64>,
+    bad_epochs: usize,
+}
+
+impl ReduceOnPlateau {
+    pub fn new(mode: Mode, factor: f64, patience: usize) -> Self {
+        Self {
+            mode,
+            factor,
+            patience,
+            min_delta: 0.0,
+            min_lr: 0.0,
+            scale: 1.0,
+            best: None,
+            bad_epochs: 0,
+        }
+    }
+}
+
+impl Schedule for ReduceOnPlateau {
+    fn learning_rate(&mut self, base: f64, _epoch: usize, _step: usize) -> f64 {
+        (base * self.scale).max(self.min_lr)
+    }
+
+    fn observe(&mut self, metric: f64) {
+        match self.best {
+            Some(best) if !self.mode.improved(metric, best, self.min_delta) => {
+                self.bad_epochs += 1;
+                if self.bad_epochs > self.patience {
+                    self.scale *= self.factor;
+                    self.bad_epochs = 0;
+                }
+            }
+            _ => {
+                self.best = Some(metric);
+                self.bad_epochs = 0;
+            }
+        }
+    }
+
+    /// the current scale, the best metric or NaN if there is none yet, and
+    /// the number of epochs since the best
+    fn state(&self) -> Vec<f64> {
+        let best = self.best.unwrap_or(f64::NAN);
+        vec![self.scale, best, self.bad_epochs as f64]
+    }
+
+    fn set_state(&mut self, state: Vec<f64>) -> bool {
+        let Ok([scale, best, bad_epochs]) = <[f64; 3]>::try_from(state) else {
+            return false;
+        };
+        self.scale = scale;
+        self.best = (!best.is_nan()).then_some(best);
+        self.bad_epochs = bad_epochs as usize;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_cosine_restarts() {
+        let mut s = CosineRestarts {
+            period: 2,
+            t_mult: 2,
+            min_lr: 0.0,
+        };
+        let got: Vec<_> = (0..7).map(|e| s.learning_rate(1.0, e, 0)).collect();
+        let want = [1.0, 0.5, 1.0, 0.853553, 0.5, 0.146447, 1.0];
+        assert_abs_diff_eq!(got.as_slice(), &want[..], epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_plateau() {
+        let mut s = ReduceOnPlateau::new(Mode::Min, 0.5, 1);
+        let mut got = Vec::new();
+        for metric in [10.0, 9.0, 9.5, 9.5, 9.5, 8.0] {
+            s.observe(metric);
+            got.push(s.learning_rate(1.0, 0, 0));
+        }
+        assert_eq!(got, vec![1.0, 1.0, 1.0, 0.5, 0.5, 0.5]);
+
+        // a copy restored from the state carries on where `s` left off
+        let mut resumed = ReduceOnPlateau::new(Mode::Min, 0.5, 1);
+        assert!(resumed.set_state(s.state()));
+        for metric in [8.5, 8.5] {
+            s.observe(metric);
+            resumed.observe(metric);
+        }
+        assert_eq!(resumed.learning_rate(1.0, 0, 0), 0.25);
+        assert_eq!(resumed.state(), s.state());
+    }
+
+    #[test]
+    fn test_warmup() {
+        let mut s = Warmup {
+            steps: 4,
+            after: Box::new(Constant),
+        };
+        let got: Vec<_> = (0..6).map(|i| s.learning_rate(1.0, 0, i)).collect();
+        assert_eq!(got, vec![0.25, 0.5, 0.75, 1.0, 1.0, 1.0]);
+    }
+}
@@ -0,0 +1,57 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+/// the independent uses of randomness in a training run, each of which gets
+/// its own generator derived from the run-level seed
+#[derive(Clone, Copy)]
+pub(crate) enum Stream {
+    Init = 1,
+    Shuffle = 2,
+    Dropout = 3,
+    Split = 4,
+}
+
+/// the SplitMix64 finalizer, used to scramble seeds so that nearby inputs give
+/// unrelated outputs
+fn splitmix(z: u64) -> u64 {
+    let z = z.wrapping_add(0x9e3779b97f4a7c15);
+    let z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// a generator for `stream` derived from the run-level `seed`. `index`
+/// distinguishes repeated uses of the same stream, such as the shuffle for
+/// each epoch, so that every epoch can be reproduced on its own when resuming
+/// from a checkpoint
+pub(crate) fn rng(seed: u64, stream: Stream, index: usize) -> StdRng {
+    let key = ((stream as u64) << 56) ^ index as u64;
+    StdRng::seed_from_u64(splitmix(splitmix(seed) ^ key))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_streams() {
+        let draw = |mut r: StdRng| r.gen::<u64>();
+        assert_eq!(
+            draw(rng(410, Stream::Shuffle, 3)),
+            draw(rng(410, Stream::Shuffle, 3))
+        );
+        assert_ne!(
+            draw(rng(410, Stream::Shuffle, 3)),
+            draw(rng(410, Stream::Shuffle, 4))
+        );
+        assert_ne!(
+            draw(rng(410, Stream::Shuffle, 0)),
+            draw(rng(410, Stream::Dropout, 0))
+        );
+        assert_ne!(
+            draw(rng(410, Stream::Init, 0)),
+            draw(rng(411, Stream::Init, 0))
+        );
+    }
+}
@@ -0,0 +1,348 @@
+//! destinations for the per-epoch metrics reported by [crate::Train::fit]
+
+use std::{
+    cell::RefCell,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/// the metrics for one epoch of training
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Record {
+    /// the seed of the network being trained
+    pub seed: u64,
+    pub epoch: usize,
+    /// the validation metric from [crate::Train::check_output]
+    pub val: f64,
+    /// the validation loss, averaged over the validation samples
+    pub val_loss: f64,
+    /// the training metric from [crate::Train::check_output], combined over
+    /// the batches in the epoch by [crate::Train::combine]
+    pub train: f64,
+    /// the training loss, averaged over the samples in the epoch
+    pub loss: f64,
+    /// the learning rate used for the last batch in the epoch
+    pub lr: f64,
+    /// the wall-clock time taken by the epoch, in seconds
+    pub seconds: f64,
+}
+
+/// a destination for the metrics from a training run
+pub trait Sink {
+    /// called once before the first epoch of a run, with the seed of the
+    /// network being trained and the epoch training starts from, which is
+    /// not zero when resuming from a checkpoint
+    fn start(&mut self, _seed: u64, _epoch: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// called at the end of every epoch
+    fn record(&mut self, record: &Record) -> io::Result<()>;
+
+    /// called at the end of every epoch with the outputs of the network for
+    /// each batch of the validation set, in order
+    fn outputs(
+        &mut self,
+        _epoch: usize,
+        _batch: usize,
+        _outputs: &[f64],
+    ) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// how a [Log] writes each [Record]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// whitespace-separated epoch, validation, and training metrics, preceded
+    /// by a `# seed` comment. this is the format expected by the `plot`
+    /// script
+    Plain,
+    /// comma-separated values with a header row
+    Csv,
+    /// one JSON object per line
+    JsonLines,
+}
+
+/// a JSON number for `x`, or null if it is not finite
+fn json(x: f64) -> String {
+    if x.is_finite() {
+        format!("{x}")
+    } else {
+        "null".to_owned()
+    }
+}
+
+impl Format {
+    pub(crate) fn header(
+        &self,
+        w: &mut dyn Write,
+        seed: u64,
+    ) -> io::Result<()> {
+        match self {
+            Format::Plain => writeln!(w, "# seed {seed}"),
+            Format::Csv => {
+                writeln!(w, "seed,epoch,val,val_loss,train,loss,lr,seconds")
+            }
+            Format::JsonLines => Ok(()),
+        }
+    }
+
+    pub(crate) fn write(
+        &self,
+        w: &mut dyn Write,
+        r: &Record,
+    ) -> io::Result<()> {
+        match self {
+            Format::Plain => {
+                writeln!(w, "{:5} {:8.2} {:8.2}", r.epoch, r.val, r.train)
+            }
+            Format::Csv => writeln!(
+                w,
+                "{},{},{},{},{},{},{},{}",
+                r.seed,
+                r.epoch,
+                r.val,
+                r.val_loss,
+                r.train,
+                r.loss,
+                r.lr,
+                r.seconds
+            ),
+            Format::JsonLines => writeln!(
+                w,
+                "{{\"seed\":{},\"epoch\":{},\"val\":{},\"val_loss\":{},\
+                 \"train\":{},\"loss\":{},\"lr\":{},\"seconds\":{}}}",
+                r.seed,
+                r.epoch,
+                json(r.val),
+                json(r.val_loss),
+                json(r.train),
+                json(r.loss),
+                json(r.lr),
+                json(r.seconds)
+            ),
+        }
+    }
+}
+
+/// open `path` for a run starting at `epoch`: a new run truncates it, and a
+/// resumed one appends to the records of the epochs before it
+fn open(path: &Path, epoch: usize) -> io::Result<File> {
+    if epoch == 0 {
+        File::create(path)
+    } else {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+}
+
+/// writes each [Record] to a file or to stdout in a given [Format]
+pub struct Log {
+    /// the file to write to, or stdout if `None`
+    path: Option<PathBuf>,
+    format: Format,
+    out: Option<Box<dyn Write>>,
+}
+
+impl Log {
+    /// write to `path`, which is created or truncated when training starts,
+    /// or appended to when training resumes from a checkpoint
+    pub fn file(path: impl Into<PathBuf>, format: Format) -> Self {
+        Self {
+            path: Some(path.into()),
+            format,
+            out: None,
+        }
+    }
+
+    pub fn stdout(format: Format) -> Self {
+        Self {
+            path: None,
+            format,
+            out: None,
+        }
+    }
+
+    fn out(&mut self) -> io::Result<&mut Box<dyn Write>> {
+        self.out
+            .as_mut()
+            .ok_or_else(|| io::Error::other("log written before start"))
+    }
+}
+
+impl Sink for Log {
+    fn start(&mut self, seed: u64, epoch: usize) -> io::Result<()> {
+        // a file being appended to already has its header
+        let (mut out, header): (Box<dyn Write>, _) = match &self.path {
+            Some(path) => {
+                let file = open(path, epoch)?;
+                let empty = file.metadata()?.len() == 0;
+                (Box::new(BufWriter::new(file)), empty)
+            }
+            None => (Box::new(io::stdout()), true),
+        };
+        if header {
+            self.format.header(&mut out, seed)?;
+        }
+        out.flush()?;
+        self.out = Some(out);
+        Ok(())
+    }
+
+    fn record(&mut self, record: &Record) -> io::Result<()> {
+        let format = self.format;
+        let out = self.out()?;
+        format.write(out, record)?;
+        // flush every epoch so the log can be followed during a long run
+        out.flush()
+    }
+}
+
+/// writes the validation outputs at the end of every epoch to a file, one
+/// line per validation batch starting with the epoch and batch numbers. like
+/// [Log::file], the file is appended to when training resumes
+pub struct Outputs {
+    path: PathBuf,
+    out: Option<BufWriter<File>>,
+}
+
+impl Outputs {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            out: None,
+        }
+    }
+}
+
+impl Sink for Outputs {
+    fn start(&mut self, _seed: u64, epoch: usize) -> io::Result<()> {
+        self.out = Some(BufWriter::new(open(&self.path, epoch)?));
+        Ok(())
+    }
+
+    fn record(&mut self, _record: &Record) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn outputs(
+        &mut self,
+        epoch: usize,
+        batch: usize,
+        outputs: &[f64],
+    ) -> io::Result<()> {
+        let Some(out) = self.out.as_mut() else {
+            return Err(io::Error::other("outputs written before start"));
+        };
+        write!(out, "{epoch} {batch}")?;
+        for x in outputs {
+            write!(out, " {x:e}")?;
+        }
+        writeln!(out)?;
+        out.flush()
+    }
+}
+
+/// keeps every [Record] in memory. clones share the same records, so keep a
+/// clone to read them back after passing this to [crate::Config::sink]
+#[derive(Clone, Debug, Default)]
+pub struct Memory {
+    records: Rc<RefCell<Vec<Record>>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the records received so far
+    pub fn records(&self) -> Vec<Record> {
+        self.records.borrow().clone()
+    }
+}
+
+impl Sink for Memory {
+    fn start(&mut self, _seed: u64, _epoch: usize) -> io::Result<()> {
+        self.records.borrow_mut().clear();
+        Ok(())
+    }
+
+    fn record(&mut self, record: &Record) -> io::Result<()> {
+        self.records.borrow_mut().push(*record);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats() {
+        let r = Record {
+            seed: 410,
+            epoch: 3,
+            val: 91.5,
+            val_loss: 0.25,
+            train: 88.25,
+            loss: 0.125,
+            lr: 0.01,
+            seconds: 1.5,
+        };
+        let render = |format: Format| {
+            let mut buf = Vec::new();
+            format.header(&mut buf, r.seed).unwrap();
+            format.write(&mut buf, &r).unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(
+            render(Format::Plain),
+            "# seed 410\n    3    91.50    88.25\n"
+        );
+        assert_eq!(
+            render(Format::Csv),
+            "seed,epoch,val,val_loss,train,loss,lr,seconds\n\
+             410,3,91.5,0.25,88.25,0.125,0.01,1.5\n"
+        );
+        assert_eq!(
+            render(Format::JsonLines),
+            "{\"seed\":410,\"epoch\":3,\"val\":91.5,\"val_loss\":0.25,\
+             \"train\":88.25,\"loss\":0.125,\"lr\":0.01,\"seconds\":1.5}\n"
+        );
+    }
+
+    /// a resumed run appends to the log without repeating the header, and a
+    /// new run starts it over
+    #[test]
+    fn test_resume() {
+        let path = crate::tests::temp_path("resume.csv");
+        let mut log = Log::file(&path, Format::Csv);
+        let run = |log: &mut Log, epochs: std::ops::Range<usize>| {
+            log.start(410, epochs.start).unwrap();
+            for epoch in epochs {
+                let r = Record {
+                    seed: 410,
+                    epoch,
+                    val: 1.0,
+                    val_loss: 1.0,
+                    train: 1.0,
+                    loss: 1.0,
+                    lr: 1.0,
+                    seconds: 1.0,
+                };
+                log.record(&r).unwrap();
+            }
+            log.out = None;
+            let got = std::fs::read_to_string(&path).unwrap();
+            got.lines()
+                .map(|l| l.split(',').nth(1).unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        run(&mut log, 0..2);
+        assert_eq!(run(&mut log, 2..4), ["epoch", "0", "1", "2", "3"]);
+        assert_eq!(run(&mut log, 0..1), ["epoch", "0"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,12 +1,325 @@
 use approx::assert_abs_diff_eq;
 
 use super::*;
+use nll::LossFunction;
+
+/// a path in the temporary directory for the file or directory `name`,
+/// unique to this process so that concurrent test runs do not collide
+pub(crate) fn temp_path(name: &str) -> std::path::PathBuf {
+    let pid = std::process::id();
+    std::env::temp_dir().join(format!("dnnosaur_test_{pid}_{name}"))
+}
+
+/// a small regression problem: the target is the mean of the two inputs
+struct Toy {
+    data: Vec<f64>,
+    labels: Vec<f64>,
+    batch_size: usize,
+}
+
+impl Toy {
+    fn new() -> Self {
+        let data: Vec<_> =
+            (0..40).map(|i| (i * 7 % 13) as f64 / 13.0).collect();
+        let labels = data.chunks(2).map(|x| (x[0] + x[1]) / 2.0).collect();
+        Self {
+            data,
+            labels,
+            batch_size: 6,
+        }
+    }
+}
+
+impl Train<f64> for Toy {
+    fn input_size(&self) -> usize {
+        2
+    }
+
+    fn output_size(&self) -> usize {
+        1
+    }
+
+    fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    fn data_size(&self) -> usize {
+        20
+    }
+
+    fn label_size(&self) -> usize {
+        1
+    }
+
+    fn train_data(&self, r: Range<usize>) -> &[f64] {
+        &self.data[r]
+    }
+
+    fn train_labels(&self, r: Range<usize>) -> &[f64] {
+        &self.labels[r]
+    }
+
+    fn test_data(&self) -> &[f64] {
+        &self.data
+    }
+
+    fn test_labels(&self) -> &[f64] {
+        &self.labels
+    }
+
+    fn check_output(&self, got: &[f64], want: &[f64]) -> f64 {
+        let sum: f64 = got.iter().zip(want).map(|(g, w)| (g - w).powi(2)).sum();
+        (sum / got.len() as f64).sqrt()
+    }
+
+    fn combine(&self, metrics: &[(f64, f64)]) -> f64 {
+        let total: f64 = metrics.iter().map(|(_, w)| w).sum();
+        (metrics.iter().map(|(m, w)| m * m * w).sum::<f64>() / total).sqrt()
+    }
+
+    fn nll(&self, inputs: Vec<f64>, targets: &[f64]) -> NllOutput {
+        nll::Mse.nll(&inputs, targets, None, 1)
+    }
+}
+
+/// the same seed reproduces a run exactly, including the shuffling and
+/// dropout, and a different seed does not
+#[test]
+fn test_seed() {
+    let run = |seed| {
+        let memory = sink::Memory::new();
+        let arch = Architecture::new().hidden(8, LossFn::Tanh).dropout(0.25);
+        let got = Toy::new()
+            .train(Config::new(5, arch).seed(seed).sink(memory.clone()));
+        let records = memory.records();
+        assert_eq!(records.len(), 5);
+        assert!(records.iter().all(|r| r.seed == seed));
+        assert_eq!(records, got.records);
+        (got.val(), got.loss())
+    };
+    assert_eq!(run(7), run(7));
+    assert_ne!(run(7), run(8));
+}
+
+/// with an unreachable `min_delta`, training stops after `patience` epochs
+/// and the weights from the first epoch are restored
+#[test]
+fn test_early_stopping() {
+    let toy = Toy::new();
+    let config = Config::new(20, LossFn::Tanh.into());
+    let mut network = toy.network(&config);
+    let got = toy.fit(
+        &mut network,
+        config.early_stopping(
+            EarlyStopping::new(Mode::Min, 3).min_delta(f64::INFINITY),
+        ),
+    );
+    assert_eq!(got.len(), 4);
+    assert_eq!(got.stop, Stop::EarlyStopping);
+    assert_eq!(network.epoch(), 4);
+    let outputs = network.predict(toy.test_data());
+    assert_eq!(toy.check_output(&outputs, toy.test_labels()), got.val()[0]);
+}
+
+/// a run resumed from a checkpoint with a default [Config] carries on with the
+/// saved optimizer, exactly matching a run that was never interrupted
+#[test]
+fn test_resume() {
+    let toy = Toy::new();
+    let path = temp_path("resume.ckpt");
+    let config = |epochs| {
+        Config::new(epochs, LossFn::Tanh.into())
+            .optimizer(optimizer::Adam::new(0.001))
+    };
+    let want = toy.train(config(4));
+    toy.train(config(2).checkpoint(&path));
+
+    let mut network = Network::load(&path, &optimizer::Adam::new(0.5)).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let got = toy.fit(&mut network, Config::new(4, LossFn::Tanh.into()));
+    assert_eq!(got.len(), 2);
+    for (g, w) in got.records.iter().zip(&want.records[2..]) {
+        assert_eq!(g.lr, 0.001);
+        assert_eq!((g.epoch, g.val, g.loss), (w.epoch, w.val, w.loss));
+    }
+}
+
+/// a run resumed from a checkpoint keeps the early-stopping patience and the
+/// best weights from before the checkpoint
+#[test]
+fn test_resume_early_stopping() {
+    let toy = Toy::new();
+    let path = temp_path("resume_early_stopping.ckpt");
+    let config = |epochs| {
+        Config::new(epochs, LossFn::Tanh.into()).early_stopping(
+            EarlyStopping::new(Mode::Min, 3).min_delta(f64::INFINITY),
+        )
+    };
+    let mut want = toy.network(&config(20));
+    toy.fit(&mut want, config(20));
+    toy.train(config(2).checkpoint(&path));
+
+    let mut got = Network::load(&path, &optimizer::Sgd::new(0.01)).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let history = toy.fit(&mut got, config(20));
+    assert_eq!(history.len(), 2);
+    assert_eq!(history.stop, Stop::EarlyStopping);
+    assert_eq!(got.epoch(), 4);
+    // the weights from the first epoch are restored in both runs
+    assert_eq!(got.predict(toy.test_data()), want.predict(toy.test_data()));
+}
+
+/// with a patience of zero, training stops at the first epoch that does not
+/// improve rather than after the first epoch
+#[test]
+fn test_zero_patience() {
+    let config = Config::new(20, LossFn::Tanh.into())
+        .early_stopping(EarlyStopping::new(Mode::Max, 0));
+    let got = Toy::new().train(config);
+    // the first epoch always counts as an improvement, and the RMSD falls in
+    // the second, which is not one in `Max` mode
+    assert_eq!(got.len(), 2);
+    assert_eq!(got.stop, Stop::EarlyStopping);
+}
+
+/// records the hooks it sees, freezes the weights by zeroing the learning
+/// rate, and stops after the second epoch
+#[derive(Clone, Default)]
+struct Hooks(std::rc::Rc<std::cell::RefCell<Vec<String>>>);
+
+impl callback::Callback for Hooks {
+    fn on_train_begin(&mut self, _network: &Network) {
+        self.0.borrow_mut().push("train".to_owned());
+    }
+
+    fn on_epoch_begin(&mut self, epoch: usize) {
+        self.0.borrow_mut().push(format!("epoch {epoch}"));
+    }
+
+    fn on_batch_begin(&mut self, _epoch: usize, _batch: usize, lr: &mut f64) {
+        *lr = 0.0;
+    }
+
+    fn on_batch_end(&mut self, _epoch: usize, batch: usize, losses: &[f64]) {
+        self.0
+            .borrow_mut()
+            .push(format!("batch {batch} {}", losses.len()));
+    }
+
+    fn on_epoch_end(
+        &mut self,
+        record: &sink::Record,
+        _network: &Network,
+    ) -> callback::Control {
+        if record.epoch == 1 {
+            callback::Control::Stop
+        } else {
+            callback::Control::Continue
+        }
+    }
+
+    fn on_train_end(&mut self, _network: &Network, history: &History) {
+        self.0.borrow_mut().push(format!("end {}", history.len()));
+    }
+}
+
+#[test]
+fn test_callbacks() {
+    let hooks = Hooks::default();
+    let got = Toy::new().train(
+        Config::new(10, LossFn::Tanh.into())
+            .no_callbacks()
+            .callback(hooks.clone()),
+    );
+    assert_eq!(got.len(), 2);
+    assert_eq!(got.stop, Stop::Callback);
+    assert_eq!(got.val()[0], got.val()[1]);
+    let batches = ["batch 0 6", "batch 1 6", "batch 2 6", "batch 3 2"];
+    let mut want = vec!["train", "epoch 0"];
+    want.extend(batches);
+    want.push("epoch 1");
+    want.extend(batches);
+    want.push("end 2");
+    assert_eq!(*hooks.0.borrow(), want);
+}
+
+/// a NaN label stops training at the first batch containing it, without a
+/// record for that epoch
+#[test]
+fn test_non_finite() {
+    let mut toy = Toy::new();
+    toy.labels[15] = f64::NAN;
+    let got = toy.train(Config::new(3, LossFn::Tanh.into()).shuffle(false));
+    assert!(got.is_empty());
+    assert_eq!(got.stop, Stop::NonFinite { epoch: 0, batch: 2 });
+}
+
+/// `drop_last` skips the short final batch, but not when it is the only one
+#[test]
+fn test_drop_last() {
+    let batches = |toy: &Toy| {
+        let hooks = Hooks::default();
+        let got = toy.train(
+            Config::new(1, LossFn::Tanh.into())
+                .drop_last(true)
+                .no_callbacks()
+                .callback(hooks.clone()),
+        );
+        assert!(got.loss()[0].is_finite() && got.train()[0].is_finite());
+        let log = hooks.0.borrow();
+        log.iter()
+            .filter(|l| l.starts_with("batch"))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let mut toy = Toy::new();
+    assert_eq!(batches(&toy), ["batch 0 6", "batch 1 6", "batch 2 6"]);
+    toy.batch_size = 32;
+    assert_eq!(batches(&toy), ["batch 0 20"]);
+}
+
+#[test]
+fn test_history() {
+    let got = Toy::new().train(Config::new(3, LossFn::Tanh.into()));
+    assert_eq!(got.len(), 3);
+    assert_eq!(got.stop, Stop::Completed);
+    for (e, r) in got.records.iter().enumerate() {
+        assert_eq!(r.epoch, e);
+        assert_eq!(r.lr, 0.01);
+        // the metric is an RMSD and the loss is an MSE over the same data
+        assert_abs_diff_eq!(r.val * r.val, r.val_loss, epsilon = 1e-12);
+    }
+    let mut csv = Vec::new();
+    got.write(&mut csv, sink::Format::Csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert_eq!(csv.lines().count(), 4);
+    assert!(csv.starts_with("seed,epoch,val,val_loss,train,loss,lr,seconds"));
+}
+
+/// the validation metrics do not depend on how the validation set is split
+/// into batches, including a short final batch
+#[test]
+fn test_val_batches() {
+    let run = |val_batch_size| {
+        Toy::new().train(
+            Config::new(2, LossFn::Tanh.into()).val_batch_size(val_batch_size),
+        )
+    };
+    let (want, got) = (run(1000), run(3));
+    assert_abs_diff_eq!(
+        got.val().as_slice(),
+        want.val().as_slice(),
+        epsilon = 1e-12
+    );
+    assert_abs_diff_eq!(
+        got.val_loss().as_slice(),
+        want.val_loss().as_slice(),
+        epsilon = 1e-12
+    );
+}
 
 #[test]
-fn test_train() {
-    let got = mnist::Data::default()
-        .read_mnist()
-        .train(3, LossFn::LeakyRelu);
-    let want = vec![88.88, 90.94, 92.07];
-    assert_abs_diff_eq!(got.as_slice(), want.as_slice(), epsilon = 1e-2);
+#[should_panic(expected = "validation batch size must be positive")]
+fn test_zero_val_batch_size() {
+    let _ = Config::new(1, LossFn::Tanh.into()).val_batch_size(0);
 }